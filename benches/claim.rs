@@ -0,0 +1,66 @@
+//! Host-side baselines for the hot paths of `Claim`, run on in-memory buffers
+//! without an SVM: `cargo bench --bench claim`.
+
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use punchcard::{CURRENT_VERSION, PUNCHCARD_MAGIC, Punchcard, PunchcardHeader};
+
+const CAPACITIES: [u64; 3] = [64, 4096, 65536];
+const BATCH_SIZES: [u64; 3] = [1, 32, 1024];
+
+fn card_bytes(capacity: u64, fill: u8) -> Vec<u8> {
+    let mut header = PunchcardHeader {
+        magic: PUNCHCARD_MAGIC,
+        authority: [1; 32],
+        capacity,
+        version: CURRENT_VERSION,
+        ..bytemuck::Zeroable::zeroed()
+    };
+    let bits = vec![fill; capacity.div_ceil(8) as usize];
+    header.claimed = bits.iter().map(|b| b.count_ones() as u64).sum();
+    header.update_checksum();
+
+    let mut data = bytemuck::bytes_of(&header).to_vec();
+    data.extend_from_slice(&bits);
+    data
+}
+
+fn claim_many(c: &mut Criterion) {
+    let mut group = c.benchmark_group("claim_many");
+    for capacity in CAPACITIES {
+        for batch in BATCH_SIZES.into_iter().filter(|&b| b <= capacity) {
+            // Spread the batch across the whole card
+            let indices: Vec<u64> = (0..batch).map(|i| i * (capacity / batch)).collect();
+            let data = card_bytes(capacity, 0);
+            group.bench_with_input(
+                BenchmarkId::new(format!("capacity_{capacity}"), batch),
+                &indices,
+                |b, indices| {
+                    b.iter_batched_ref(
+                        || data.clone(),
+                        |data| {
+                            let mut card = Punchcard::from_bytes(data).unwrap();
+                            card.claim_many(indices).unwrap();
+                        },
+                        BatchSize::SmallInput,
+                    )
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn count_ones(c: &mut Criterion) {
+    let mut group = c.benchmark_group("count_ones");
+    for capacity in CAPACITIES {
+        let mut data = card_bytes(capacity, 0b0101_0101);
+        group.bench_function(BenchmarkId::from_parameter(capacity), |b| {
+            let card = Punchcard::from_bytes(&mut data).unwrap();
+            b.iter(|| card.bits.count_ones())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, claim_many, count_ones);
+criterion_main!(benches);
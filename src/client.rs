@@ -0,0 +1,892 @@
+//! Off-chain helpers for building and sizing punchcard accounts.
+
+use std::ops::Range;
+
+use pinocchio::program_error::ProgramError;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction as SolanaInstruction},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::Keypair,
+    signer::Signer,
+};
+
+use crate::{Capacity, Instruction, Punchcard, PunchcardHeader, split_refund};
+
+pub use crate::return_data::{
+    decode_claim, decode_claim_expired, decode_claim_percent, decode_claim_random, decode_diagnose,
+    decode_ping, decode_query, decode_touch_claim,
+};
+
+pub fn program_id() -> Pubkey {
+    Pubkey::new_from_array(crate::ID)
+}
+
+/// Wire encoding of `instruction`, exactly as the program decodes it.
+pub fn instruction_data(instruction: &Instruction) -> Vec<u8> {
+    borsh::to_vec(instruction).expect("instructions always serialize")
+}
+
+pub fn create_instruction(payer: &Pubkey, punchcard: &Pubkey, capacity: u64) -> SolanaInstruction {
+    SolanaInstruction {
+        program_id: program_id(),
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(*punchcard, true),
+            AccountMeta::new_readonly(Pubkey::new_from_array(pinocchio_system::ID), false),
+        ],
+        data: instruction_data(&Instruction::Create {
+            capacity,
+            redemption_window: 0,
+            close_recipient: [0; 32],
+            extra_lamports: 0,
+        }),
+    }
+}
+
+pub fn claim_instruction(
+    authority: &Pubkey,
+    punchcard: &Pubkey,
+    indices: Vec<u64>,
+) -> SolanaInstruction {
+    SolanaInstruction {
+        program_id: program_id(),
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: instruction_data(&Instruction::Claim { indices }),
+    }
+}
+
+pub fn claim_range_instruction(
+    authority: &Pubkey,
+    punchcard: &Pubkey,
+    range: Range<u64>,
+) -> SolanaInstruction {
+    SolanaInstruction {
+        program_id: program_id(),
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: instruction_data(&Instruction::ClaimRange {
+            start: range.start,
+            end: range.end,
+        }),
+    }
+}
+
+/// Address and bump of the card `payer` creates with `CreatePda` and `seed`.
+pub fn pda_address(payer: &Pubkey, seed: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[crate::PUNCHCARD_SEED, payer.as_ref(), &seed[..]],
+        &program_id(),
+    )
+}
+
+/// `CreatePda` for the card at `pda_address(payer, seed)`. Only `payer` signs.
+pub fn create_pda_instruction(payer: &Pubkey, seed: [u8; 32], capacity: u64) -> SolanaInstruction {
+    let (punchcard, _) = pda_address(payer, &seed);
+    SolanaInstruction {
+        program_id: program_id(),
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(punchcard, false),
+            AccountMeta::new_readonly(Pubkey::new_from_array(pinocchio_system::ID), false),
+        ],
+        data: instruction_data(&Instruction::CreatePda { capacity, seed }),
+    }
+}
+
+/// Instructions and signers for creating a card owned by `payer` and claiming
+/// `indices` on it in a single transaction.
+pub fn create_and_claim<'a>(
+    payer: &'a Keypair,
+    punchcard: &'a Keypair,
+    capacity: u64,
+    indices: Vec<u64>,
+) -> (Vec<SolanaInstruction>, Vec<&'a dyn Signer>) {
+    let instructions = vec![
+        create_instruction(&payer.pubkey(), &punchcard.pubkey(), capacity),
+        claim_instruction(&payer.pubkey(), &punchcard.pubkey(), indices),
+    ];
+    (instructions, vec![payer as &dyn Signer, punchcard])
+}
+
+/// Description of one `Instruction` variant as it appears on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VariantSchema {
+    pub name: &'static str,
+    /// Leading borsh byte identifying the variant.
+    pub discriminant: u8,
+    /// `(field name, borsh type)` pairs in serialization order.
+    pub fields: &'static [(&'static str, &'static str)],
+}
+
+/// Every `Instruction` variant in discriminant order.
+pub fn instruction_schema() -> &'static [VariantSchema] {
+    const SCHEMA: &[VariantSchema] = &[
+        VariantSchema {
+            name: "Create",
+            discriminant: 0,
+            fields: &[
+                ("capacity", "u64"),
+                ("redemption_window", "u64"),
+                ("close_recipient", "[u8; 32]"),
+                ("extra_lamports", "u64"),
+            ],
+        },
+        VariantSchema {
+            name: "Claim",
+            discriminant: 1,
+            fields: &[("indices", "Vec<u64>")],
+        },
+        VariantSchema {
+            name: "ClaimExpired",
+            discriminant: 2,
+            fields: &[("index", "u64")],
+        },
+        VariantSchema {
+            name: "Diagnose",
+            discriminant: 3,
+            fields: &[],
+        },
+        VariantSchema {
+            name: "ClaimAll",
+            discriminant: 4,
+            fields: &[],
+        },
+        VariantSchema {
+            name: "ClaimThenFail",
+            discriminant: 5,
+            fields: &[("indices", "Vec<u64>")],
+        },
+        VariantSchema {
+            name: "SetNote",
+            discriminant: 6,
+            fields: &[("note", "String")],
+        },
+        VariantSchema {
+            name: "Ping",
+            discriminant: 7,
+            fields: &[],
+        },
+        VariantSchema {
+            name: "CreateIdempotent",
+            discriminant: 8,
+            fields: &[
+                ("capacity", "u64"),
+                ("redemption_window", "u64"),
+                ("close_recipient", "[u8; 32]"),
+            ],
+        },
+        VariantSchema {
+            name: "ClaimAndTransfer",
+            discriminant: 9,
+            fields: &[("indices", "Vec<u64>"), ("new_authority", "[u8; 32]")],
+        },
+        VariantSchema {
+            name: "ClaimNext",
+            discriminant: 10,
+            fields: &[("count", "u64")],
+        },
+        VariantSchema {
+            name: "ResetAndResize",
+            discriminant: 11,
+            fields: &[("new_capacity", "u64")],
+        },
+        VariantSchema {
+            name: "TouchClaim",
+            discriminant: 12,
+            fields: &[("index", "u64")],
+        },
+        VariantSchema {
+            name: "ClaimRandom",
+            discriminant: 13,
+            fields: &[("seed", "u64")],
+        },
+        VariantSchema {
+            name: "ClaimPercent",
+            discriminant: 14,
+            fields: &[("bps", "u16")],
+        },
+        VariantSchema {
+            name: "Contribute",
+            discriminant: 15,
+            fields: &[("lamports", "u64")],
+        },
+        VariantSchema {
+            name: "RequireClaimed",
+            discriminant: 16,
+            fields: &[("index", "u64")],
+        },
+        VariantSchema {
+            name: "ClaimUntil",
+            discriminant: 17,
+            fields: &[("indices", "Vec<u64>"), ("valid_until_slot", "u64")],
+        },
+        VariantSchema {
+            name: "CreateInverted",
+            discriminant: 18,
+            fields: &[
+                ("capacity", "u64"),
+                ("redemption_window", "u64"),
+                ("close_recipient", "[u8; 32]"),
+            ],
+        },
+        VariantSchema {
+            name: "TransferAuthority",
+            discriminant: 19,
+            fields: &[("new_authority", "[u8; 32]")],
+        },
+        VariantSchema {
+            name: "Close",
+            discriminant: 20,
+            fields: &[],
+        },
+        VariantSchema {
+            name: "Unclaim",
+            discriminant: 21,
+            fields: &[("indices", "Vec<u64>")],
+        },
+        VariantSchema {
+            name: "ClaimRange",
+            discriminant: 22,
+            fields: &[("start", "u64"), ("end", "u64")],
+        },
+        VariantSchema {
+            name: "Recount",
+            discriminant: 23,
+            fields: &[],
+        },
+        VariantSchema {
+            name: "CreatePda",
+            discriminant: 24,
+            fields: &[("capacity", "u64"), ("seed", "[u8; 32]")],
+        },
+        VariantSchema {
+            name: "Resize",
+            discriminant: 25,
+            fields: &[("new_capacity", "u64")],
+        },
+        VariantSchema {
+            name: "CreateNamed",
+            discriminant: 26,
+            fields: &[
+                ("capacity", "u64"),
+                ("redemption_window", "u64"),
+                ("close_recipient", "[u8; 32]"),
+                ("name", "String"),
+            ],
+        },
+        VariantSchema {
+            name: "CreateExpiring",
+            discriminant: 27,
+            fields: &[
+                ("capacity", "u64"),
+                ("redemption_window", "u64"),
+                ("close_recipient", "[u8; 32]"),
+                ("expiry_slot", "u64"),
+            ],
+        },
+        VariantSchema {
+            name: "Query",
+            discriminant: 28,
+            fields: &[],
+        },
+        VariantSchema {
+            name: "CreateWithCloseAuthority",
+            discriminant: 29,
+            fields: &[
+                ("capacity", "u64"),
+                ("redemption_window", "u64"),
+                ("close_recipient", "[u8; 32]"),
+                ("close_authority", "[u8; 32]"),
+            ],
+        },
+        VariantSchema {
+            name: "SetPaused",
+            discriminant: 30,
+            fields: &[("paused", "bool")],
+        },
+        VariantSchema {
+            name: "MultiClaim",
+            discriminant: 31,
+            fields: &[("claims", "Vec<(u8, Vec<u64>)>")],
+        },
+        VariantSchema {
+            name: "AddDelegate",
+            discriminant: 32,
+            fields: &[("delegate", "[u8; 32]")],
+        },
+        VariantSchema {
+            name: "RemoveDelegate",
+            discriminant: 33,
+            fields: &[("delegate", "[u8; 32]")],
+        },
+        VariantSchema {
+            name: "ClaimWithProof",
+            discriminant: 34,
+            fields: &[("index", "u64"), ("proof", "Vec<[u8; 32]>")],
+        },
+        VariantSchema {
+            name: "SetMerkleRoot",
+            discriminant: 35,
+            fields: &[("merkle_root", "[u8; 32]")],
+        },
+        VariantSchema {
+            name: "ClaimSigned",
+            discriminant: 36,
+            fields: &[("index", "u64")],
+        },
+        VariantSchema {
+            name: "SetClaimSigner",
+            discriminant: 37,
+            fields: &[("signer_pubkey", "[u8; 32]")],
+        },
+        VariantSchema {
+            name: "ClaimForFee",
+            discriminant: 38,
+            fields: &[("indices", "Vec<u64>"), ("fee", "u64")],
+        },
+        VariantSchema {
+            name: "Toggle",
+            discriminant: 39,
+            fields: &[("indices", "Vec<u64>")],
+        },
+        VariantSchema {
+            name: "CreateWithRecipients",
+            discriminant: 40,
+            fields: &[
+                ("capacity", "u64"),
+                ("redemption_window", "u64"),
+                ("close_recipient", "[u8; 32]"),
+            ],
+        },
+        VariantSchema {
+            name: "SetRecipients",
+            discriminant: 41,
+            fields: &[("start", "u64"), ("recipients", "Vec<[u8; 32]>")],
+        },
+        VariantSchema {
+            name: "Migrate",
+            discriminant: 42,
+            fields: &[],
+        },
+    ];
+    SCHEMA
+}
+
+/// Size in bytes of a punchcard account without bound recipients, computed
+/// independently of [`crate::Punchcard::account_len`] in wide arithmetic.
+/// Binding recipients adds 32 bytes per index.
+pub fn account_size(capacity: u64, redemption_window: u64) -> Option<usize> {
+    let header = size_of::<PunchcardHeader>() as u128;
+    let bits = (capacity as u128).div_ceil(8);
+    let stamps = if redemption_window == 0 {
+        0
+    } else {
+        capacity as u128 * 2
+    };
+    usize::try_from(header + bits + stamps).ok()
+}
+
+/// Why account data could not be decoded into claimed flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// Shorter than a punchcard header.
+    TruncatedHeader,
+    /// Too short to hold the bitset for the header's capacity.
+    TruncatedBits,
+    /// Two cards that were expected to match have different capacities.
+    CapacityMismatch,
+}
+
+/// One flag per index below `capacity`, `true` where the index is claimed.
+/// Padding bits in the final bitset byte are not included.
+pub fn parse_claimed_flags(data: &[u8]) -> Result<Vec<bool>, ParseError> {
+    let header_len = size_of::<PunchcardHeader>();
+    let header: PunchcardHeader =
+        bytemuck::pod_read_unaligned(data.get(..header_len).ok_or(ParseError::TruncatedHeader)?);
+    let bits_len =
+        usize::try_from(header.capacity.div_ceil(8)).map_err(|_| ParseError::TruncatedBits)?;
+    let bits = data[header_len..]
+        .get(..bits_len)
+        .ok_or(ParseError::TruncatedBits)?;
+    Ok((0..header.capacity)
+        .map(|index| bits[(index / 8) as usize] & (1 << (index % 8)) != 0)
+        .collect())
+}
+
+/// Contiguous runs of indices claimed on `source` but not on `target`, in
+/// ascending order. Claims are only ever added, never removed.
+pub fn missing_runs(source: &[u8], target: &[u8]) -> Result<Vec<Range<u64>>, ParseError> {
+    let source = parse_claimed_flags(source)?;
+    let target = parse_claimed_flags(target)?;
+    if source.len() != target.len() {
+        return Err(ParseError::CapacityMismatch);
+    }
+
+    let mut runs: Vec<Range<u64>> = Vec::new();
+    for (index, (&want, &have)) in (0u64..).zip(source.iter().zip(&target)) {
+        if !want || have {
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run) if run.end == index => run.end += 1,
+            _ => runs.push(index..index + 1),
+        }
+    }
+    Ok(runs)
+}
+
+/// `ClaimRange` instructions, one per missing run, bringing the card at
+/// `target_key` up to the claimed set of `source`, given both cards' account data.
+pub fn sync_instructions(
+    authority: &Pubkey,
+    target_key: &Pubkey,
+    source: &[u8],
+    target: &[u8],
+) -> Result<Vec<SolanaInstruction>, ParseError> {
+    Ok(missing_runs(source, target)?
+        .into_iter()
+        .map(|run| claim_range_instruction(authority, target_key, run))
+        .collect())
+}
+
+/// Whether claiming `indices` on a card with `account_data` would fill it and so
+/// close it. Runs the program's own claim logic on a copy of the data, so a claim
+/// that would fail predicts `false`. A card with a `close_authority` only closes
+/// if that key co-signs the claim, which this does not check.
+pub fn will_close(account_data: &[u8], indices: &[u64]) -> bool {
+    let mut data = account_data.to_vec();
+    let Ok(mut card) = Punchcard::from_bytes(&mut data) else {
+        return false;
+    };
+    card.claim_many(indices).is_ok() && card.is_full()
+}
+
+/// An owned copy of a punchcard account's fields, for clients that read
+/// accounts fetched over RPC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PunchcardView {
+    pub authority: Pubkey,
+    pub capacity: u64,
+    pub claimed: u64,
+    /// The bitset, including padding bits in the final byte.
+    pub bits: Vec<u8>,
+}
+
+impl PunchcardView {
+    /// Parses account data with the same checks as [`Punchcard::from_bytes`],
+    /// failing wherever the program would refuse to load the card.
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut data = data.to_vec();
+        let card = Punchcard::from_bytes(&mut data)?;
+        Ok(Self {
+            authority: Pubkey::new_from_array(card.header.authority),
+            capacity: card.header.capacity,
+            claimed: card.header.claimed,
+            bits: card.bits.0.to_vec(),
+        })
+    }
+
+    /// Whether `index` is claimed, failing with `IndexOutOfBounds` if it is
+    /// `>= capacity`.
+    pub fn is_claimed(&self, index: u64) -> Result<bool, ProgramError> {
+        if index >= self.capacity {
+            return Err(crate::Error::IndexOutOfBounds.into());
+        }
+        Ok(self.bits[(index / 8) as usize] & (1 << (index % 8)) != 0)
+    }
+}
+
+/// Lamports returned to the close recipient when a card holding
+/// `account_lamports` closes: the balance less the contributors' refunds, as
+/// split by [`split_refund`]. `None` if the program would not load the card.
+pub fn refund_estimate(account_data: &[u8], account_lamports: u64) -> Option<u64> {
+    let mut data = account_data.to_vec();
+    let card = Punchcard::from_bytes(&mut data).ok()?;
+    Some(split_refund(account_lamports, &card.header.contributors).1)
+}
+
+/// Rent-exempt minimum for a card created with `Create` at `capacity` and no
+/// redemption window, sized by the program's own [`Punchcard::account_len`].
+/// `None` for capacities `Create` rejects.
+pub fn rent_for_capacity(capacity: u64, rent: &Rent) -> Option<u64> {
+    let capacity = Capacity::new(capacity).ok()?;
+    let space = Punchcard::account_len(capacity.get(), 0, false).ok()?;
+    Some(rent.minimum_balance(space))
+}
+
+/// Whether `account_lamports` cover rent for a card of the given configuration,
+/// with `bind_recipients` for cards made with `CreateWithRecipients`. Returns
+/// `false` for configurations too large to allocate.
+pub fn is_rent_exempt(
+    account_lamports: u64,
+    capacity: u64,
+    redemption_window: u64,
+    bind_recipients: bool,
+    rent: &Rent,
+) -> bool {
+    Punchcard::account_len(capacity, redemption_window, bind_recipients)
+        .is_ok_and(|size| account_lamports >= rent.minimum_balance(size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::card_bytes;
+
+    // Exhaustive on purpose: adding a variant fails to compile until the schema
+    // test below learns about it.
+    fn variant_name(ix: &Instruction) -> &'static str {
+        match ix {
+            Instruction::Create { .. } => "Create",
+            Instruction::Claim { .. } => "Claim",
+            Instruction::ClaimExpired { .. } => "ClaimExpired",
+            Instruction::Diagnose => "Diagnose",
+            Instruction::ClaimAll => "ClaimAll",
+            Instruction::ClaimThenFail { .. } => "ClaimThenFail",
+            Instruction::SetNote { .. } => "SetNote",
+            Instruction::Ping => "Ping",
+            Instruction::CreateIdempotent { .. } => "CreateIdempotent",
+            Instruction::ClaimAndTransfer { .. } => "ClaimAndTransfer",
+            Instruction::ClaimNext { .. } => "ClaimNext",
+            Instruction::ResetAndResize { .. } => "ResetAndResize",
+            Instruction::TouchClaim { .. } => "TouchClaim",
+            Instruction::ClaimRandom { .. } => "ClaimRandom",
+            Instruction::ClaimPercent { .. } => "ClaimPercent",
+            Instruction::Contribute { .. } => "Contribute",
+            Instruction::RequireClaimed { .. } => "RequireClaimed",
+            Instruction::ClaimUntil { .. } => "ClaimUntil",
+            Instruction::CreateInverted { .. } => "CreateInverted",
+            Instruction::TransferAuthority { .. } => "TransferAuthority",
+            Instruction::Close => "Close",
+            Instruction::Unclaim { .. } => "Unclaim",
+            Instruction::ClaimRange { .. } => "ClaimRange",
+            Instruction::Recount => "Recount",
+            Instruction::CreatePda { .. } => "CreatePda",
+            Instruction::Resize { .. } => "Resize",
+            Instruction::CreateNamed { .. } => "CreateNamed",
+            Instruction::CreateExpiring { .. } => "CreateExpiring",
+            Instruction::Query => "Query",
+            Instruction::CreateWithCloseAuthority { .. } => "CreateWithCloseAuthority",
+            Instruction::SetPaused { .. } => "SetPaused",
+            Instruction::MultiClaim { .. } => "MultiClaim",
+            Instruction::AddDelegate { .. } => "AddDelegate",
+            Instruction::RemoveDelegate { .. } => "RemoveDelegate",
+            Instruction::ClaimWithProof { .. } => "ClaimWithProof",
+            Instruction::SetMerkleRoot { .. } => "SetMerkleRoot",
+            Instruction::ClaimSigned { .. } => "ClaimSigned",
+            Instruction::SetClaimSigner { .. } => "SetClaimSigner",
+            Instruction::ClaimForFee { .. } => "ClaimForFee",
+            Instruction::Toggle { .. } => "Toggle",
+            Instruction::CreateWithRecipients { .. } => "CreateWithRecipients",
+            Instruction::SetRecipients { .. } => "SetRecipients",
+            Instruction::Migrate => "Migrate",
+        }
+    }
+
+    /// One value of every variant. Add new variants here and both wire format
+    /// tests below pick them up.
+    fn samples() -> Vec<Instruction> {
+        vec![
+            Instruction::Create {
+                capacity: 1,
+                redemption_window: 0,
+                close_recipient: [0; 32],
+                extra_lamports: 0,
+            },
+            Instruction::Claim { indices: vec![0] },
+            Instruction::ClaimExpired { index: 0 },
+            Instruction::Diagnose,
+            Instruction::ClaimAll,
+            Instruction::ClaimThenFail { indices: vec![0] },
+            Instruction::SetNote {
+                note: String::new(),
+            },
+            Instruction::Ping,
+            Instruction::CreateIdempotent {
+                capacity: 1,
+                redemption_window: 0,
+                close_recipient: [0; 32],
+            },
+            Instruction::ClaimAndTransfer {
+                indices: vec![0],
+                new_authority: [1; 32],
+            },
+            Instruction::ClaimNext { count: 1 },
+            Instruction::ResetAndResize { new_capacity: 1 },
+            Instruction::TouchClaim { index: 0 },
+            Instruction::ClaimRandom { seed: 0 },
+            Instruction::ClaimPercent { bps: 0 },
+            Instruction::Contribute { lamports: 1 },
+            Instruction::RequireClaimed { index: 0 },
+            Instruction::ClaimUntil {
+                indices: vec![0],
+                valid_until_slot: 0,
+            },
+            Instruction::CreateInverted {
+                capacity: 1,
+                redemption_window: 0,
+                close_recipient: [0; 32],
+            },
+            Instruction::TransferAuthority {
+                new_authority: [1; 32],
+            },
+            Instruction::Close,
+            Instruction::Unclaim {
+                indices: vec![1, 2],
+            },
+            Instruction::ClaimRange { start: 1, end: 2 },
+            Instruction::Recount,
+            Instruction::CreatePda {
+                capacity: 8,
+                seed: [2; 32],
+            },
+            Instruction::Resize { new_capacity: 16 },
+            Instruction::CreateNamed {
+                capacity: 8,
+                redemption_window: 0,
+                close_recipient: [0; 32],
+                name: "Spring".to_string(),
+            },
+            Instruction::CreateExpiring {
+                capacity: 8,
+                redemption_window: 0,
+                close_recipient: [0; 32],
+                expiry_slot: 100,
+            },
+            Instruction::Query,
+            Instruction::CreateWithCloseAuthority {
+                capacity: 8,
+                redemption_window: 0,
+                close_recipient: [0; 32],
+                close_authority: [5; 32],
+            },
+            Instruction::SetPaused { paused: true },
+            Instruction::MultiClaim {
+                claims: vec![(0, vec![1, 2]), (1, vec![0])],
+            },
+            Instruction::AddDelegate { delegate: [6; 32] },
+            Instruction::RemoveDelegate { delegate: [6; 32] },
+            Instruction::ClaimWithProof {
+                index: 3,
+                proof: vec![[7; 32], [8; 32]],
+            },
+            Instruction::SetMerkleRoot {
+                merkle_root: [9; 32],
+            },
+            Instruction::ClaimSigned { index: 4 },
+            Instruction::SetClaimSigner {
+                signer_pubkey: [10; 32],
+            },
+            Instruction::ClaimForFee {
+                indices: vec![2, 3],
+                fee: 5_000,
+            },
+            Instruction::Toggle {
+                indices: vec![1, 3],
+            },
+            Instruction::CreateWithRecipients {
+                capacity: 8,
+                redemption_window: 0,
+                close_recipient: [11; 32],
+            },
+            Instruction::SetRecipients {
+                start: 2,
+                recipients: vec![[12; 32], [13; 32]],
+            },
+            Instruction::Migrate,
+        ]
+    }
+
+    #[test]
+    fn test_instruction_schema_matches_wire_format() {
+        let samples = samples();
+
+        let schema = instruction_schema();
+        assert_eq!(schema.len(), samples.len());
+        for (variant, sample) in schema.iter().zip(&samples) {
+            assert_eq!(variant.name, variant_name(sample));
+            assert_eq!(borsh::to_vec(sample).unwrap()[0], variant.discriminant);
+        }
+    }
+
+    #[test]
+    fn test_client_data_round_trips_through_program_decoder() {
+        for sample in samples() {
+            let decoded = crate::decode_instruction(&instruction_data(&sample)).unwrap();
+            assert_eq!(decoded, sample);
+        }
+
+        let payer = Pubkey::new_unique();
+        let card = Pubkey::new_unique();
+        assert_eq!(
+            crate::decode_instruction(&create_instruction(&payer, &card, 42).data).unwrap(),
+            Instruction::Create {
+                capacity: 42,
+                redemption_window: 0,
+                close_recipient: [0; 32],
+                extra_lamports: 0,
+            }
+        );
+        assert_eq!(
+            crate::decode_instruction(&claim_instruction(&payer, &card, vec![3, 1]).data).unwrap(),
+            Instruction::Claim {
+                indices: vec![3, 1]
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_claimed_flags_stops_at_capacity() {
+        // Claimed: 0, 7, 10. The padding bits above index 10 are set to make
+        // sure they are not reported.
+        let data = card_bytes(11, &[0b1000_0001, 0b1111_1100]);
+
+        let flags = parse_claimed_flags(&data).unwrap();
+        assert_eq!(flags.len(), 11);
+        let claimed: Vec<usize> = (0..flags.len()).filter(|&i| flags[i]).collect();
+        assert_eq!(claimed, [0, 7, 10]);
+
+        assert_eq!(
+            parse_claimed_flags(&data[..data.len() - 1]),
+            Err(ParseError::TruncatedBits)
+        );
+        assert_eq!(
+            parse_claimed_flags(&data[..10]),
+            Err(ParseError::TruncatedHeader)
+        );
+    }
+
+    #[test]
+    fn test_sync_instructions_from_empty_target() {
+        // Claimed: 1, 2, 3, 7, 8, 13
+        let source = card_bytes(14, &[0b1000_1110, 0b0010_0001]);
+        let target = card_bytes(14, &[0, 0]);
+
+        assert_eq!(
+            missing_runs(&source, &target).unwrap(),
+            [1..4, 7..9, 13..14]
+        );
+
+        let authority = Pubkey::new_unique();
+        let card = Pubkey::new_unique();
+        let instructions = sync_instructions(&authority, &card, &source, &target).unwrap();
+        let ranges: Vec<_> = instructions
+            .iter()
+            .map(|ix| match borsh::from_slice(&ix.data) {
+                Ok(Instruction::ClaimRange { start, end }) => start..end,
+                _ => panic!("expected a claim range"),
+            })
+            .collect();
+        assert_eq!(ranges, [1..4, 7..9, 13..14]);
+
+        // Indices the target already has, or that only the target has, are left alone
+        let target = card_bytes(14, &[0b0001_0010, 0]);
+        assert_eq!(
+            missing_runs(&source, &target).unwrap(),
+            [2..4, 7..9, 13..14]
+        );
+
+        let smaller = card_bytes(8, &[0]);
+        assert_eq!(
+            missing_runs(&source, &smaller),
+            Err(ParseError::CapacityMismatch)
+        );
+    }
+
+    #[test]
+    fn test_will_close() {
+        // Claimed: 0..=6, leaving 7, 8 and 9
+        let data = card_bytes(10, &[0b0111_1111, 0]);
+
+        assert!(will_close(&data, &[7, 8, 9]));
+        assert!(will_close(&data, &[9, 7, 8]));
+        assert!(!will_close(&data, &[7, 8]));
+        // These would fail on-chain, so nothing closes
+        assert!(!will_close(&data, &[7, 8, 8]));
+        assert!(!will_close(&data, &[6, 7, 8, 9]));
+        assert!(!will_close(&data, &[7, 8, 10]));
+        assert!(!will_close(&data[..data.len() - 1], &[7, 8, 9]));
+
+        assert_eq!(refund_estimate(&data, 1_447_680), Some(1_447_680));
+        assert_eq!(refund_estimate(&data[..100], 1_447_680), None);
+    }
+
+    #[test]
+    fn test_refund_estimate_leaves_out_contributors() {
+        let mut data = card_bytes(10, &[0, 0]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+        card.header.contributors[0] = crate::Contribution {
+            key: [2; 32],
+            lamports: 2_000_000,
+        };
+        card.header.contributors[1] = crate::Contribution {
+            key: [3; 32],
+            lamports: 5_000_000,
+        };
+
+        assert_eq!(refund_estimate(&data, 8_447_680), Some(1_447_680));
+        // A balance short of the deposits all goes to the contributors
+        assert_eq!(refund_estimate(&data, 7_000_000), Some(0));
+    }
+
+    #[test]
+    fn test_is_rent_exempt() {
+        let rent = Rent::default();
+        let minimum = rent.minimum_balance(account_size(100, 0).unwrap());
+
+        assert!(is_rent_exempt(minimum, 100, 0, false, &rent));
+        assert!(!is_rent_exempt(minimum - 1, 100, 0, false, &rent));
+        // Stamps make the same capacity more expensive
+        assert!(!is_rent_exempt(minimum, 100, 50, false, &rent));
+        // So do bound recipients, at 32 bytes per index
+        assert!(!is_rent_exempt(minimum, 100, 0, true, &rent));
+        let bound = rent.minimum_balance(account_size(100, 0).unwrap() + 32 * 100);
+        assert!(is_rent_exempt(bound, 100, 0, true, &rent));
+        assert!(!is_rent_exempt(u64::MAX, u64::MAX, 0, false, &rent));
+    }
+
+    #[test]
+    fn test_rent_for_capacity_matches_space() {
+        let rent = Rent::default();
+        for capacity in [1, 7, 8, 9, 1_000, 65_536, crate::MAX_CAPACITY] {
+            let space = Punchcard::space(capacity).unwrap();
+            assert_eq!(
+                rent_for_capacity(capacity, &rent),
+                Some(rent.minimum_balance(space)),
+                "capacity {capacity}"
+            );
+        }
+        assert_eq!(rent_for_capacity(0, &rent), None);
+        assert_eq!(rent_for_capacity(crate::MAX_CAPACITY + 1, &rent), None);
+        assert_eq!(rent_for_capacity(u64::MAX, &rent), None);
+    }
+
+    #[test]
+    fn test_account_size_matches_space() {
+        let mut capacities: Vec<u64> = (0..=1024).collect();
+        for shift in 0..64 {
+            let power = 1u64 << shift;
+            capacities.extend([power - 1, power, power.saturating_add(1)]);
+        }
+        capacities.extend(u64::MAX - 16..=u64::MAX);
+
+        for capacity in capacities {
+            assert_eq!(
+                account_size(capacity, 0),
+                Punchcard::space(capacity).ok(),
+                "capacity {capacity}"
+            );
+            assert_eq!(
+                account_size(capacity, 1),
+                Punchcard::stamped_space(capacity).ok(),
+                "stamped capacity {capacity}"
+            );
+        }
+    }
+}
@@ -1,160 +1,4319 @@
 use pinocchio::{
-    ProgramResult, account_info::AccountInfo, entrypoint, program_error::ProgramError,
-    pubkey::Pubkey, sysvars::Sysvar,
+    ProgramResult,
+    account_info::AccountInfo,
+    entrypoint,
+    instruction::{Seed, Signer},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvars::Sysvar,
 };
-use pinocchio_system::instructions::CreateAccount;
+use pinocchio_system::instructions::{CreateAccount, Transfer};
 
 pub const ID: Pubkey = five8_const::decode_32_const("pcWKVSdcdDUKabPz4pVfaQ2jMod1kWv3LqeQivjKXiF");
 
+/// Leading bytes of every punchcard account.
+pub const PUNCHCARD_MAGIC: [u8; 8] = *b"PUNCHCRD";
+
+/// Header layout version written by `Punchcard::initialize`. Cards of any other
+/// version are rejected with `UnsupportedVersion` rather than misread; earlier
+/// versions were never released.
+///
+/// Cards created before the magic was added have no version: the original
+/// `LEGACY_HEADER_LEN`-byte header of `authority`, `capacity` and `claimed`,
+/// which `Migrate` upgrades in place.
+pub const CURRENT_VERSION: u8 = 6;
+
+/// First seed of cards created with `CreatePda`, which live at
+/// `[PUNCHCARD_SEED, payer, seed]`.
+pub const PUNCHCARD_SEED: &[u8] = b"punchcard";
+
+/// The Ed25519 signature verification program, whose instructions `ClaimSigned`
+/// reads through the instructions sysvar.
+pub const ED25519_PROGRAM_ID: Pubkey =
+    five8_const::decode_32_const("Ed25519SigVerify111111111111111111111111111");
+
+/// Width, in slots, of one claim stamp bucket. Stamps are `u16`, so a card can
+/// record claims for roughly `u16::MAX * STAMP_BUCKET_SLOTS` slots after creation.
+pub const STAMP_BUCKET_SLOTS: u64 = 1024;
+
+/// Failure bits returned by `Instruction::Diagnose`. A healthy card reports zero.
+pub const DIAGNOSE_BAD_OWNER: u8 = 1 << 0;
+pub const DIAGNOSE_BAD_HEADER_SIZE: u8 = 1 << 1;
+pub const DIAGNOSE_BAD_BITSET_LEN: u8 = 1 << 2;
+pub const DIAGNOSE_CLAIMED_OVER_CAPACITY: u8 = 1 << 3;
+pub const DIAGNOSE_POPCOUNT_MISMATCH: u8 = 1 << 4;
+pub const DIAGNOSE_CHECKSUM_MISMATCH: u8 = 1 << 5;
+pub const DIAGNOSE_BAD_VERSION: u8 = 1 << 6;
+pub const DIAGNOSE_BAD_MAGIC: u8 = 1 << 7;
+
+#[cfg(feature = "client")]
+pub mod client;
+pub mod merkle;
+pub mod return_data;
+
+/// Largest account the runtime will allocate.
+pub const MAX_ACCOUNT_LEN: usize = 10 * 1024 * 1024;
+
+/// Length of the current header, after which the bitset starts.
+pub const PUNCHCARD_HEADER_LEN: usize = size_of::<PunchcardHeader>();
+
+/// Length of the header of cards from before the magic, which `Migrate` upgrades.
+pub const LEGACY_HEADER_LEN: usize = 48;
+
+/// Byte offsets into a punchcard account, for readers that parse raw account
+/// data instead of going through `Punchcard::from_bytes`.
+pub const AUTHORITY_OFFSET: usize = 8;
+pub const CAPACITY_OFFSET: usize = 40;
+pub const CLAIMED_OFFSET: usize = 48;
+pub const BITS_OFFSET: usize = PUNCHCARD_HEADER_LEN;
+
+/// Largest capacity whose bitset still fits in a single account.
+pub const MAX_CAPACITY: u64 = ((MAX_ACCOUNT_LEN - PUNCHCARD_HEADER_LEN) * 8) as u64;
+
 // --- State ---
 
+/// A validated card capacity in `1..=MAX_CAPACITY`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capacity(u64);
+
+impl Capacity {
+    pub fn new(capacity: u64) -> Result<Self, Error> {
+        if capacity == 0 || capacity > MAX_CAPACITY {
+            return Err(Error::InvalidCapacity);
+        }
+        Ok(Self(capacity))
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
 #[repr(C)]
 #[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy)]
 pub struct PunchcardHeader {
+    /// Always `PUNCHCARD_MAGIC`; tells punchcards apart from other accounts the
+    /// program may own.
+    pub magic: [u8; 8],
     pub authority: [u8; 32],
     pub capacity: u64,
     pub claimed: u64,
+    pub created_slot: u64,
+    /// Slots a claim stays redeemable for. Zero disables claim stamps entirely.
+    pub redemption_window: u64,
+    /// CRC-32 over `authority`, `capacity` and `claimed`.
+    pub checksum: u32,
+    /// Bump seed of a card created with `CreatePda`; zero for keypair cards.
+    pub bump: u8,
+    /// Layout version, `CURRENT_VERSION` for new cards.
+    pub version: u8,
+    /// 1 while `SetPaused` has frozen claims, else 0.
+    pub paused: u8,
+    /// 1 for cards made with `CreateWithRecipients`, which keep a recipient per
+    /// index after the stamps, else 0.
+    pub bind_recipients: u8,
+    /// Free-form UTF-8 note set by the authority, zero-padded.
+    pub note: [u8; 64],
+    /// Account that receives the rent when the card closes. Zero means the authority.
+    pub close_recipient: [u8; 32],
+    /// Claims ever made, including ones since cleared by a reset. Never decreases.
+    pub total_claims: u64,
+    /// Lamports deposited with `Contribute`, refunded pro rata on close. Unused
+    /// entries have a zero key.
+    pub contributors: [Contribution; MAX_CONTRIBUTORS],
+    /// Last slot claims are accepted in. Zero means the card never expires.
+    pub expiry_slot: u64,
+    /// Key that must sign for the card to close. Zero means the authority.
+    pub close_authority: [u8; 32],
+    /// Keys besides the authority that may claim. Unused entries are zero.
+    pub delegates: [[u8; 32]; MAX_DELEGATES],
+    /// Root of the `merkle` tree of indices anyone may claim with
+    /// `ClaimWithProof`. Zero disables proof claims.
+    pub merkle_root: [u8; 32],
+    /// Key whose Ed25519 signature authorizes a `ClaimSigned`. Zero disables
+    /// signed claims.
+    pub signer_pubkey: [u8; 32],
+}
+
+/// Most distinct contributors a card records.
+pub const MAX_CONTRIBUTORS: usize = 4;
+
+/// Most delegates a card records.
+pub const MAX_DELEGATES: usize = 4;
+
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Default)]
+pub struct Contribution {
+    pub key: [u8; 32],
+    pub lamports: u64,
+}
+
+/// Splits a closing card's `balance` between its contributors in proportion to
+/// what each put in, capped at their combined contributions. Returns each
+/// contributor's refund and what is left for the close recipient, including
+/// rounding dust.
+pub fn split_refund(
+    balance: u64,
+    contributors: &[Contribution; MAX_CONTRIBUTORS],
+) -> ([u64; MAX_CONTRIBUTORS], u64) {
+    let total: u128 = contributors.iter().map(|c| c.lamports as u128).sum();
+    let mut refunds = [0; MAX_CONTRIBUTORS];
+    if total == 0 {
+        return (refunds, balance);
+    }
+
+    let pool = total.min(balance as u128);
+    for (refund, contribution) in refunds.iter_mut().zip(contributors) {
+        // At most `pool`, which fits in a u64
+        *refund = (pool * contribution.lamports as u128 / total) as u64;
+    }
+    (refunds, balance - refunds.iter().sum::<u64>())
+}
+
+impl PunchcardHeader {
+    pub fn compute_checksum(&self) -> u32 {
+        // The bitset is deliberately left out to keep this cheap on large cards
+        crc32(&bytemuck::bytes_of(self)[8..56])
+    }
+
+    pub fn update_checksum(&mut self) {
+        self.checksum = self.compute_checksum();
+    }
+
+    /// Whether claims are refused at `slot` because the card has expired.
+    pub fn is_expired(&self, slot: u64) -> bool {
+        self.expiry_slot != 0 && slot > self.expiry_slot
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused != 0
+    }
+
+    pub fn binds_recipients(&self) -> bool {
+        self.bind_recipients != 0
+    }
+
+    /// Whether `key` may claim: the authority or one of its delegates.
+    pub fn can_claim(&self, key: &Pubkey) -> bool {
+        *key == self.authority || self.delegates.contains(key)
+    }
+
+    /// The key allowed to close the card: `close_authority` if set, otherwise
+    /// the authority.
+    pub fn closer(&self) -> &Pubkey {
+        if self.close_authority == [0; 32] {
+            &self.authority
+        } else {
+            &self.close_authority
+        }
+    }
+
+    pub fn note_str(&self) -> &str {
+        let len = self.note.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        core::str::from_utf8(&self.note[..len]).unwrap_or_default()
+    }
+}
+
+// Summarizes the fields people check when a test fails; the raw arrays
+// (delegates, contributors, ...) are left out.
+impl core::fmt::Debug for PunchcardHeader {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PunchcardHeader")
+            .field("authority", &Base58(&self.authority))
+            .field("capacity", &self.capacity)
+            .field("claimed", &self.claimed)
+            .field("total_claims", &self.total_claims)
+            .field("version", &self.version)
+            .field("paused", &self.is_paused())
+            .field("redemption_window", &self.redemption_window)
+            .field("expiry_slot", &self.expiry_slot)
+            .field("note", &self.note_str())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Formats a key as base58, the way wallets and explorers show it.
+struct Base58<'a>(&'a [u8; 32]);
+
+impl core::fmt::Debug for Base58<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+        // Little-endian base 58 digits; 32 bytes never need more than 44
+        let mut digits = [0u8; 44];
+        let mut len = 0;
+        for &byte in self.0 {
+            let mut carry = byte as u32;
+            for digit in &mut digits[..len] {
+                carry += (*digit as u32) << 8;
+                *digit = (carry % 58) as u8;
+                carry /= 58;
+            }
+            while carry > 0 {
+                digits[len] = (carry % 58) as u8;
+                len += 1;
+                carry /= 58;
+            }
+        }
+
+        // Each leading zero byte is written as a '1'
+        for _ in self.0.iter().take_while(|&&byte| byte == 0) {
+            f.write_str("1")?;
+        }
+        for &digit in digits[..len].iter().rev() {
+            core::fmt::Write::write_char(f, ALPHABET[digit as usize] as char)?;
+        }
+        Ok(())
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Keccak-256 of the concatenation of `parts`.
+fn keccak(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    #[cfg(target_os = "solana")]
+    unsafe {
+        pinocchio::syscalls::sol_keccak256(
+            parts.as_ptr() as *const u8,
+            parts.len() as u64,
+            hash.as_mut_ptr(),
+        );
+    }
+    #[cfg(not(target_os = "solana"))]
+    {
+        use sha3::Digest;
+        let mut hasher = sha3::Keccak256::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hash.copy_from_slice(&hasher.finalize());
+    }
+    hash
 }
 
 pub struct Bits<'a>(&'a mut [u8]);
 
 impl Bits<'_> {
-    pub fn get(&self, index: u64) -> bool {
-        let byte = self.0[(index / 8) as usize];
-        (byte & (1 << (index % 8))) != 0
+    /// Fails with `IndexOutOfBounds` if `index` is past the end of the bitset.
+    /// Bits past `capacity` in the final byte are padding and are not caught here.
+    pub fn get(&self, index: u64) -> Result<bool, ProgramError> {
+        let byte = self.byte(index)?;
+        Ok((byte & (1 << (index % 8))) != 0)
+    }
+
+    pub fn set(&mut self, index: u64) -> ProgramResult {
+        *self.byte_mut(index)? |= 1 << (index % 8);
+        Ok(())
+    }
+
+    pub fn clear(&mut self, index: u64) -> ProgramResult {
+        *self.byte_mut(index)? &= !(1 << (index % 8));
+        Ok(())
+    }
+
+    /// Flips the bit at `index`, returning whether it is now set.
+    pub fn toggle(&mut self, index: u64) -> Result<bool, ProgramError> {
+        let byte = self.byte_mut(index)?;
+        *byte ^= 1 << (index % 8);
+        Ok(*byte & (1 << (index % 8)) != 0)
+    }
+
+    fn byte(&self, index: u64) -> Result<u8, ProgramError> {
+        usize::try_from(index / 8)
+            .ok()
+            .and_then(|i| self.0.get(i).copied())
+            .ok_or(Error::IndexOutOfBounds.into())
+    }
+
+    fn byte_mut(&mut self, index: u64) -> Result<&mut u8, ProgramError> {
+        usize::try_from(index / 8)
+            .ok()
+            .and_then(|i| self.0.get_mut(i))
+            .ok_or(Error::IndexOutOfBounds.into())
+    }
+
+    /// Sets every bit below `capacity`, leaving the padding bits of the final byte clear.
+    pub fn set_all(&mut self, capacity: u64) {
+        let full = (capacity / 8) as usize;
+        self.0[..full].fill(0xFF);
+        if capacity % 8 != 0 {
+            self.0[full] |= (1u8 << (capacity % 8)) - 1;
+        }
+    }
+
+    pub fn clear_all(&mut self) {
+        self.0.fill(0);
+    }
+
+    /// Sets every bit in `[start, end)`, masking partial bytes at both ends and
+    /// filling the whole bytes between them at once.
+    pub fn set_range(&mut self, start: u64, end: u64) {
+        if start >= end {
+            return;
+        }
+        let first = (start / 8) as usize;
+        let last = ((end - 1) / 8) as usize;
+        let head = 0xFFu8 << (start % 8);
+        let tail = 0xFFu8 >> (7 - (end - 1) % 8);
+        if first == last {
+            self.0[first] |= head & tail;
+            return;
+        }
+        self.0[first] |= head;
+        self.0[first + 1..last].fill(0xFF);
+        self.0[last] |= tail;
+    }
+
+    /// Counts every set bit in the bitset.
+    ///
+    /// Works a `u64` word at a time: one load and popcount per eight bytes instead of
+    /// eight of each, which is what dominates on multi-kilobyte cards. Reading words via
+    /// `from_le_bytes` keeps this correct for unaligned slices; the tail is done bytewise.
+    pub fn count_ones(&self) -> u64 {
+        let mut words = self.0.chunks_exact(8);
+        let mut count: u64 = words
+            .by_ref()
+            .map(|word| u64::from_le_bytes(word.try_into().unwrap()).count_ones() as u64)
+            .sum();
+        for byte in words.remainder() {
+            count += byte.count_ones() as u64;
+        }
+        count
+    }
+
+    /// Counts set bits in `[start, end)`, masking partial bytes at both ends.
+    pub fn count_ones_in_range(&self, start: u64, end: u64) -> u64 {
+        if start >= end {
+            return 0;
+        }
+        let first = (start / 8) as usize;
+        let last = ((end - 1) / 8) as usize;
+        let mut count = 0;
+        for i in first..=last {
+            let mut mask = 0xFFu8;
+            if i == first {
+                mask &= 0xFF << (start % 8);
+            }
+            if i == last {
+                mask &= 0xFF >> (7 - (end - 1) % 8);
+            }
+            count += (self.0[i] & mask).count_ones() as u64;
+        }
+        count
+    }
+
+    pub fn count_zeros_in_range(&self, start: u64, end: u64) -> u64 {
+        end.saturating_sub(start) - self.count_ones_in_range(start, end)
+    }
+
+    /// Claimed indices below `capacity`, in ascending order. Empty bytes cost one
+    /// check each rather than eight.
+    pub fn iter_claimed(&self, capacity: u64) -> impl Iterator<Item = u64> + '_ {
+        self.iter_set(0, capacity)
+    }
+
+    /// Unclaimed indices below `capacity`, in ascending order. Full bytes cost one
+    /// check each rather than eight.
+    pub fn iter_unclaimed(&self, capacity: u64) -> impl Iterator<Item = u64> + '_ {
+        self.iter_set(0xFF, capacity)
     }
 
-    pub fn set(&mut self, index: u64) {
-        self.0[(index / 8) as usize] |= 1 << (index % 8);
+    /// Indices below `capacity` whose bit is set once each byte is XORed with
+    /// `invert`, taking the lowest remaining bit of a byte until none are left.
+    fn iter_set(&self, invert: u8, capacity: u64) -> impl Iterator<Item = u64> + '_ {
+        self.0
+            .iter()
+            .enumerate()
+            .flat_map(move |(i, &byte)| {
+                let mut bits = byte ^ invert;
+                core::iter::from_fn(move || {
+                    if bits == 0 {
+                        return None;
+                    }
+                    let bit = bits.trailing_zeros() as u64;
+                    bits &= bits - 1;
+                    Some(i as u64 * 8 + bit)
+                })
+            })
+            .take_while(move |&index| index < capacity)
     }
 }
 
+/// How a new card's bitset starts out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InitialFill {
+    /// Nothing claimed.
+    Empty,
+    /// Every index claimed, e.g. for cards that hand slots out with `Unclaim`.
+    Full,
+}
+
 pub struct Punchcard<'a> {
     pub header: &'a mut PunchcardHeader,
     pub bits: Bits<'a>,
+    /// One little-endian `u16` bucket per slot, empty when `redemption_window == 0`.
+    pub stamps: &'a mut [u8],
+    /// One 32-byte recipient per slot, zero where none is bound, and empty unless
+    /// the header `binds_recipients`.
+    pub recipients: &'a mut [u8],
+}
+
+// The bitset can be megabytes, so only its count is shown
+impl core::fmt::Debug for Punchcard<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Punchcard")
+            .field("header", &self.header)
+            .field(
+                "claimed_bits",
+                &self.bits.count_ones_in_range(0, self.header.capacity),
+            )
+            .field("stamped", &!self.stamps.is_empty())
+            .field("bound", &!self.recipients.is_empty())
+            .finish()
+    }
 }
 
 impl<'a> Punchcard<'a> {
-    pub fn space(capacity: u64) -> usize {
-        size_of::<PunchcardHeader>() + ((capacity as usize + 7) / 8)
+    // Lengths are worked out in `u64` and only then narrowed, so a 32-bit host
+    // agrees with the 64-bit runtime wherever the result fits its `usize`.
+
+    pub fn bitset_len(capacity: u64) -> Option<usize> {
+        usize::try_from(capacity.div_ceil(8)).ok()
+    }
+
+    /// Account length of a card without stamps. Fails with `InvalidCapacity` if
+    /// it does not fit in a `usize`.
+    pub fn space(capacity: u64) -> Result<usize, ProgramError> {
+        Self::narrow(
+            capacity
+                .div_ceil(8)
+                .checked_add(PUNCHCARD_HEADER_LEN as u64),
+        )
+    }
+
+    /// Account length of a card with a stamp per index, failing like `space`.
+    pub fn stamped_space(capacity: u64) -> Result<usize, ProgramError> {
+        Self::narrow(
+            capacity
+                .checked_mul(2)
+                .and_then(|stamps| stamps.checked_add(capacity.div_ceil(8)))
+                .and_then(|len| len.checked_add(PUNCHCARD_HEADER_LEN as u64)),
+        )
+    }
+
+    fn narrow(len: Option<u64>) -> Result<usize, ProgramError> {
+        len.and_then(|len| usize::try_from(len).ok())
+            .ok_or(Error::InvalidCapacity.into())
+    }
+
+    /// Total account length for a card with the given configuration. Fails with
+    /// `InvalidCapacity` past `MAX_ACCOUNT_LEN`, which a card binding recipients
+    /// reaches at a far lower capacity.
+    pub fn account_len(
+        capacity: u64,
+        redemption_window: u64,
+        bind_recipients: bool,
+    ) -> Result<usize, ProgramError> {
+        let len = if redemption_window == 0 {
+            Self::space(capacity)?
+        } else {
+            Self::stamped_space(capacity)?
+        };
+        let recipients = if bind_recipients {
+            capacity.checked_mul(32)
+        } else {
+            Some(0)
+        };
+        let len =
+            Self::narrow(recipients.and_then(|recipients| recipients.checked_add(len as u64)))?;
+        if len > MAX_ACCOUNT_LEN {
+            return Err(Error::InvalidCapacity.into());
+        }
+        Ok(len)
+    }
+
+    /// Writes `header` and a bitset filled per `fill` into freshly allocated
+    /// `data`, setting the magic, version, `claimed` and checksum to match. The final
+    /// byte's padding bits are always left clear.
+    pub fn initialize(
+        data: &'a mut [u8],
+        mut header: PunchcardHeader,
+        fill: InitialFill,
+    ) -> Result<Self, ProgramError> {
+        if data.len() < size_of::<PunchcardHeader>() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        let (header_bytes, rest) = data.split_at_mut(size_of::<PunchcardHeader>());
+        let bits_len = usize::try_from(header.capacity.div_ceil(8))
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        let mut bits = Bits(
+            rest.get_mut(..bits_len)
+                .ok_or(ProgramError::InvalidAccountData)?,
+        );
+        bits.clear_all();
+
+        header.magic = PUNCHCARD_MAGIC;
+        header.version = CURRENT_VERSION;
+        header.claimed = match fill {
+            InitialFill::Empty => 0,
+            InitialFill::Full => {
+                bits.set_all(header.capacity);
+                header.capacity
+            }
+        };
+        header.update_checksum();
+        header_bytes.copy_from_slice(bytemuck::bytes_of(&header));
+
+        Self::from_bytes(data)
     }
 
-    pub fn from_bytes(data: &'a mut [u8]) -> Self {
-        let (header, bits) = data.split_at_mut(size_of::<PunchcardHeader>());
-        Self {
-            header: bytemuck::from_bytes_mut(header),
+    pub fn from_bytes(data: &'a mut [u8]) -> Result<Self, ProgramError> {
+        let card = Self::from_bytes_unchecked_counts(data)?;
+        if card.header.claimed > card.header.capacity {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(card)
+    }
+
+    /// Like `from_bytes`, but allows bytes past the end of the card and returns
+    /// them separately, for programs that co-locate their own data after it.
+    pub fn from_bytes_with_tail(data: &'a mut [u8]) -> Result<(Self, &'a mut [u8]), ProgramError> {
+        let header = data
+            .get(..size_of::<PunchcardHeader>())
+            .ok_or(ProgramError::AccountDataTooSmall)?;
+        let header: PunchcardHeader = bytemuck::pod_read_unaligned(header);
+        let len = Self::account_len(
+            header.capacity,
+            header.redemption_window,
+            header.binds_recipients(),
+        )
+        .ok()
+        .filter(|&len| len <= data.len())
+        .ok_or(ProgramError::InvalidAccountData)?;
+
+        let (card, tail) = data.split_at_mut(len);
+        Ok((Self::from_bytes(card)?, tail))
+    }
+
+    /// Like `from_bytes`, but accepts `claimed > capacity` so recovery tooling can
+    /// load an over-counted card and repair it. Sizes are still validated: data too
+    /// short for a header is `AccountDataTooSmall`, while a header whose capacity
+    /// does not match the data length is `InvalidAccountData`.
+    pub fn from_bytes_unchecked_counts(data: &'a mut [u8]) -> Result<Self, ProgramError> {
+        let len = data.len();
+        if len < size_of::<PunchcardHeader>() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        let (header, rest) = data.split_at_mut(size_of::<PunchcardHeader>());
+        let header: &mut PunchcardHeader = bytemuck::from_bytes_mut(header);
+        if header.magic != PUNCHCARD_MAGIC {
+            return Err(Error::BadMagic.into());
+        }
+        if header.version != CURRENT_VERSION {
+            return Err(Error::UnsupportedVersion.into());
+        }
+        if header.checksum != header.compute_checksum() {
+            return Err(Error::ChecksumMismatch.into());
+        }
+
+        let bound = header.binds_recipients();
+        if Self::account_len(header.capacity, header.redemption_window, bound) != Ok(len) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let (bits, rest) = rest.split_at_mut(header.capacity.div_ceil(8) as usize);
+        let recipients_len = if bound {
+            header.capacity as usize * 32
+        } else {
+            0
+        };
+        let (stamps, recipients) = rest.split_at_mut(rest.len() - recipients_len);
+
+        Ok(Self {
+            header,
             bits: Bits(bits),
+            stamps,
+            recipients,
+        })
+    }
+
+    /// Number of unclaimed indices. Saturates rather than underflowing on an
+    /// over-counted card.
+    pub fn remaining(&self) -> u64 {
+        self.header.capacity.saturating_sub(self.header.claimed)
+    }
+
+    /// Whether every index is claimed, the point at which claims close the card.
+    pub fn is_full(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Whether `index` is claimed, failing with `IndexOutOfBounds` if it is
+    /// `>= capacity`.
+    pub fn is_claimed(&self, index: u64) -> Result<bool, ProgramError> {
+        if index >= self.header.capacity {
+            return Err(Error::IndexOutOfBounds.into());
         }
+        self.bits.get(index)
     }
 
     pub fn claim(&mut self, index: u64) -> ProgramResult {
-        if self.bits.get(index) {
-            return Err(Error::AlreadyClaimed.into_program_error());
+        // Bits at or past `capacity` in the final byte are padding and must stay clear
+        if index >= self.header.capacity {
+            return Err(Error::IndexOutOfBounds.into());
+        }
+        if self.bits.get(index)? {
+            return Err(Error::AlreadyClaimed.into());
         }
-        self.bits.set(index);
-        self.header.claimed += 1;
+        let claimed = self.claimed_after(1)?;
+        self.bits.set(index)?;
+        self.header.claimed = claimed;
+        self.header.total_claims = self.header.total_claims.saturating_add(1);
         Ok(())
     }
-}
-
-// --- Instructions ---
-
-#[derive(borsh::BorshSerialize, borsh::BorshDeserialize)]
-pub enum Instruction {
-    Create { capacity: u64 },
-    Claim { indices: Vec<u64> },
-}
 
-// --- Errors ---
+    /// `claimed` after `count` more claims. `from_bytes` rejects over-claimed
+    /// cards, so passing `capacity` here only trips on a bug or a counter that has
+    /// drifted from the bitset.
+    fn claimed_after(&self, count: u64) -> Result<u64, ProgramError> {
+        let claimed = self
+            .header
+            .claimed
+            .checked_add(count)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        if claimed > self.header.capacity {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(claimed)
+    }
 
-#[repr(u32)]
-pub enum Error {
-    InvalidAuthority = 0,
-    IndexOutOfBounds = 1,
-    AlreadyClaimed = 2,
-}
+    /// Clears the bits of `indices`, which must be distinct and in bounds.
+    fn clear_bits(&mut self, indices: &[u64]) {
+        for &i in indices {
+            self.bits.0[(i / 8) as usize] &= !(1 << (i % 8));
+        }
+    }
 
-impl Error {
-    pub fn into_program_error(self) -> ProgramError {
-        ProgramError::Custom(self as u32)
+    /// `claimed` after one claim is reversed.
+    fn claimed_before(&self) -> Result<u64, ProgramError> {
+        self.header
+            .claimed
+            .checked_sub(1)
+            .ok_or(ProgramError::ArithmeticOverflow)
     }
-}
 
-// --- Processor ---
+    /// Claims every index in `[start, end)`, failing if any of them is already
+    /// claimed.
+    pub fn claim_range(&mut self, start: u64, end: u64) -> ProgramResult {
+        if start > end {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        if end > self.header.capacity {
+            return Err(Error::IndexOutOfBounds.into());
+        }
+        if self.bits.count_ones_in_range(start, end) != 0 {
+            return Err(Error::AlreadyClaimed.into());
+        }
+        let claimed = self.claimed_after(end - start)?;
+        self.bits.set_range(start, end);
+        self.header.claimed = claimed;
+        self.header.total_claims = self.header.total_claims.saturating_add(end - start);
+        Ok(())
+    }
 
-#[cfg(not(feature = "no-entrypoint"))]
-entrypoint!(process);
+    /// Resets `claimed` to the number of claimed indices in the bitset, ignoring
+    /// padding bits past `capacity`.
+    pub fn recount(&mut self) {
+        self.header.claimed = self.bits.count_ones_in_range(0, self.header.capacity);
+    }
 
-pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
-    match borsh::from_slice(data).map_err(|_| ProgramError::InvalidInstructionData)? {
-        Instruction::Create { capacity } => create(program_id, accounts, capacity),
-        Instruction::Claim { indices } => claim(program_id, accounts, &indices),
+    /// Reverses a claim on `index`. `total_claims` is left alone, since the claim
+    /// still happened.
+    pub fn unclaim(&mut self, index: u64) -> ProgramResult {
+        if index >= self.header.capacity {
+            return Err(Error::IndexOutOfBounds.into());
+        }
+        if !self.bits.get(index)? {
+            return Err(Error::NotClaimed.into());
+        }
+        let claimed = self.claimed_before()?;
+        self.bits.clear(index)?;
+        self.header.claimed = claimed;
+        Ok(())
     }
-}
 
-fn create(program_id: &Pubkey, accounts: &[AccountInfo], capacity: u64) -> ProgramResult {
-    let [payer, punchcard, _system] = accounts else {
-        return Err(ProgramError::NotEnoughAccountKeys);
-    };
+    /// Claims `index` if it is unclaimed and unclaims it otherwise, returning
+    /// whether it is now claimed. Only a claim counts towards `total_claims`.
+    pub fn toggle(&mut self, index: u64) -> Result<bool, ProgramError> {
+        if index >= self.header.capacity {
+            return Err(Error::IndexOutOfBounds.into());
+        }
+        let claim = !self.bits.get(index)?;
+        let claimed = if claim {
+            self.claimed_after(1)?
+        } else {
+            self.claimed_before()?
+        };
+        self.bits.toggle(index)?;
+        self.header.claimed = claimed;
+        if claim {
+            self.header.total_claims = self.header.total_claims.saturating_add(1);
+        }
+        Ok(claim)
+    }
 
-    let space = Punchcard::space(capacity);
-    let rent = pinocchio::sysvars::rent::Rent::get()?.minimum_balance(space);
+    /// Claims every remaining slot, returning how many were newly claimed.
+    pub fn claim_all(&mut self, slot: u64) -> Result<u64, ProgramError> {
+        let capacity = self.header.capacity;
+        let newly_claimed = self.bits.count_zeros_in_range(0, capacity);
+        let claimed = self.claimed_after(newly_claimed)?;
+        if self.header.redemption_window != 0 {
+            for i in 0..capacity {
+                if self.bits.get(i) == Ok(false) {
+                    self.stamp(i, slot);
+                }
+            }
+        }
+        self.bits.set_all(capacity);
+        self.header.claimed = claimed;
+        self.header.total_claims = self.header.total_claims.saturating_add(newly_claimed);
+        Ok(newly_claimed)
+    }
 
-    CreateAccount {
-        from: payer,
-        to: punchcard,
-        lamports: rent,
-        space: space as u64,
-        owner: program_id,
+    /// The lowest unclaimed index, or `None` if every index is claimed.
+    pub fn find_first_unclaimed(&self) -> Option<u64> {
+        self.find_next_unclaimed(0)
     }
-    .invoke()?;
 
-    let mut data = punchcard.try_borrow_mut_data()?;
-    let card = Punchcard::from_bytes(&mut data);
-    card.header.authority = *payer.key();
-    card.header.capacity = capacity;
-    card.header.claimed = 0;
+    /// The lowest unclaimed index at or after `from`, or `None` if there is none.
+    /// Unlike `next_unclaimed_from` this never wraps, so workers can each scan
+    /// their own part of the card.
+    pub fn find_next_unclaimed(&self, from: u64) -> Option<u64> {
+        if from >= self.header.capacity {
+            return None;
+        }
+        let first = (from / 8) as usize;
+        // Indices below `from` in its byte count as claimed
+        let before = (1u8 << (from % 8)) - 1;
+        let (i, byte) = self.bits.0[first..]
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| (first + i, if i == 0 { byte | before } else { byte }))
+            .find(|&(_, byte)| byte != 0xFF)?;
+        // Only the final byte can hold padding, so a hit past `capacity` means full
+        Some(i as u64 * 8 + byte.trailing_ones() as u64)
+            .filter(|&index| index < self.header.capacity)
+    }
 
-    Ok(())
-}
+    /// Up to `n` of the lowest unclaimed indices, in ascending order.
+    pub fn next_unclaimed(&self, n: usize) -> Vec<u64> {
+        let capacity = self.header.capacity;
+        let mut found = Vec::with_capacity(n.min(self.remaining() as usize));
 
-fn claim(program_id: &Pubkey, accounts: &[AccountInfo], indices: &[u64]) -> ProgramResult {
-    let [authority, punchcard] = accounts else {
-        return Err(ProgramError::NotEnoughAccountKeys);
-    };
+        for (i, &byte) in self.bits.0.iter().enumerate() {
+            if found.len() == n {
+                break;
+            }
+            if byte == 0xFF {
+                continue;
+            }
+            for bit in 0..8 {
+                let index = i as u64 * 8 + bit;
+                if index >= capacity || found.len() == n {
+                    break;
+                }
+                if byte & (1 << bit) == 0 {
+                    found.push(index);
+                }
+            }
+        }
+        found
+    }
 
-    if !authority.is_signer() {
-        return Err(ProgramError::MissingRequiredSignature);
+    /// The first unclaimed index at or after `start`, wrapping around to the
+    /// beginning, or `None` if every index is claimed.
+    pub fn next_unclaimed_from(&self, start: u64) -> Option<u64> {
+        let capacity = self.header.capacity;
+        if capacity == 0 {
+            return None;
+        }
+        let start = start % capacity;
+        (start..capacity)
+            .chain(0..start)
+            .find(|&i| self.bits.get(i) == Ok(false))
     }
-    if !punchcard.is_owned_by(program_id) {
-        return Err(ProgramError::IncorrectProgramId);
+
+    /// Number of claimed slots in `[start, end)`.
+    pub fn claimed_in_range(&self, start: u64, end: u64) -> Result<u64, ProgramError> {
+        if start > end || end > self.header.capacity {
+            return Err(Error::IndexOutOfBounds.into());
+        }
+        Ok(self.bits.count_ones_in_range(start, end))
     }
 
-    let (capacity, claimed) = {
-        let mut data = punchcard.try_borrow_mut_data()?;
-        let mut card = Punchcard::from_bytes(&mut data);
+    /// Number of unclaimed slots in `[start, end)`.
+    pub fn unclaimed_in_range(&self, start: u64, end: u64) -> Result<u64, ProgramError> {
+        if start > end || end > self.header.capacity {
+            return Err(Error::IndexOutOfBounds.into());
+        }
+        Ok(self.bits.count_zeros_in_range(start, end))
+    }
 
-        if card.header.authority != *authority.key() {
-            return Err(Error::InvalidAuthority.into_program_error());
+    /// Root of the `merkle` tree over the claimed indices in ascending order, so
+    /// `merkle::verify` can check any of them against it. An unpaired node is
+    /// carried up unchanged. An empty card has an all-zero root.
+    pub fn claimed_merkle_root(&self) -> [u8; 32] {
+        let mut level: Vec<[u8; 32]> = (0..self.header.capacity)
+            .filter(|&i| self.bits.get(i) == Ok(true))
+            .map(merkle::leaf)
+            .collect();
+        if level.is_empty() {
+            return [0; 32];
+        }
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => merkle::parent(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
         }
+        level[0]
+    }
 
+    /// Checks `indices` the way `claim_many` does without changing the card:
+    /// fails with `Full` on a full card, then on the first index, in order,
+    /// that is out of bounds or already claimed. Repeated indices are left to
+    /// `claim_many`, which rejects them without changing the card either.
+    pub fn check_claimable(&self, indices: &[u64]) -> ProgramResult {
+        if self.is_full() {
+            return Err(Error::Full.into());
+        }
+        let capacity = self.header.capacity;
+        if capacity.div_ceil(8) > self.bits.0.len() as u64 {
+            return Err(ProgramError::InvalidAccountData);
+        }
         for &i in indices {
-            if i >= card.header.capacity {
-                return Err(Error::IndexOutOfBounds.into_program_error());
+            if i >= capacity {
+                return Err(Error::IndexOutOfBounds.into());
+            }
+            if self.bits.0[(i / 8) as usize] & (1 << (i % 8)) != 0 {
+                return Err(Error::AlreadyClaimed.into());
             }
-            card.claim(i)?;
         }
+        Ok(())
+    }
 
-        (card.header.capacity, card.header.claimed)
-    };
+    /// Claims every index in `indices`, failing on the first out-of-bounds,
+    /// already-claimed or repeated index. A failed claim leaves the card as it
+    /// was.
+    pub fn claim_many(&mut self, indices: &[u64]) -> ProgramResult {
+        self.check_claimable(indices)?;
+
+        for (position, &i) in indices.iter().enumerate() {
+            // SAFETY: `check_claimable` checked every index is below `capacity` and the
+            // bitset holds at least `capacity.div_ceil(8)` bytes, so `i / 8` is always in
+            // bounds.
+            let byte = unsafe { self.bits.0.get_unchecked_mut((i / 8) as usize) };
+            let mask = 1 << (i % 8);
+            if *byte & mask != 0 {
+                // Every bit was clear before the loop, so `i` repeats an earlier index,
+                // all of which are distinct and can be cleared again
+                self.clear_bits(&indices[..position]);
+                return Err(Error::DuplicateIndex.into());
+            }
+            *byte |= mask;
+        }
+        // Only distinct indices reach the count, so a repeat is never mistaken
+        // for a drifted counter
+        let claimed = match self.claimed_after(indices.len() as u64) {
+            Ok(claimed) => claimed,
+            Err(error) => {
+                self.clear_bits(indices);
+                return Err(error);
+            }
+        };
+        self.header.claimed = claimed;
+        self.header.total_claims = self
+            .header
+            .total_claims
+            .saturating_add(indices.len() as u64);
+        Ok(())
+    }
 
-    if claimed == capacity {
-        let punchcard_lamports = punchcard.lamports();
-        *authority.try_borrow_mut_lamports()? += punchcard_lamports;
-        *punchcard.try_borrow_mut_lamports()? = 0;
-        punchcard.try_borrow_mut_data()?.fill(0);
-        punchcard.close()?;
+    /// Records `slot` as the claim time of `index`, rounded down to its bucket.
+    pub fn stamp(&mut self, index: u64, slot: u64) {
+        if self.header.redemption_window == 0 {
+            return;
+        }
+        let bucket = slot.saturating_sub(self.header.created_slot) / STAMP_BUCKET_SLOTS;
+        let bucket = u16::try_from(bucket).unwrap_or(u16::MAX);
+        let at = index as usize * 2;
+        self.stamps[at..at + 2].copy_from_slice(&bucket.to_le_bytes());
     }
 
-    Ok(())
+    /// Whether the claim on `index` can no longer be redeemed at `slot`.
+    pub fn claim_expired(&self, index: u64, slot: u64) -> bool {
+        if self.header.redemption_window == 0 {
+            return false;
+        }
+        let at = index as usize * 2;
+        let bucket = u16::from_le_bytes([self.stamps[at], self.stamps[at + 1]]);
+        let claimed_slot = self
+            .header
+            .created_slot
+            .saturating_add(bucket as u64 * STAMP_BUCKET_SLOTS);
+        slot > claimed_slot.saturating_add(self.header.redemption_window)
+    }
+
+    /// The recipient bound to `index`, or `None` if there is none.
+    pub fn recipient(&self, index: u64) -> Option<&Pubkey> {
+        let at = usize::try_from(index)
+            .ok()
+            .filter(|&i| i < self.recipients.len() / 32)?
+            * 32;
+        let key: &Pubkey = self.recipients[at..at + 32].try_into().ok()?;
+        (*key != [0; 32]).then_some(key)
+    }
+
+    /// Binds `recipients` to the indices from `start` on. A zero key unbinds.
+    pub fn bind_recipients(&mut self, start: u64, recipients: &[Pubkey]) -> ProgramResult {
+        if !self.header.binds_recipients() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        start
+            .checked_add(recipients.len() as u64)
+            .filter(|&end| end <= self.header.capacity)
+            .ok_or(Error::IndexOutOfBounds)?;
+        let at = start as usize * 32;
+        let slots = self.recipients[at..].chunks_exact_mut(32);
+        for (slot, recipient) in slots.zip(recipients) {
+            slot.copy_from_slice(recipient);
+        }
+        Ok(())
+    }
+
+    /// Whether `indices` are all bound to `key`, which lets it claim them
+    /// without being the authority or a delegate.
+    pub fn bound_to(&self, key: &Pubkey, indices: &[u64]) -> bool {
+        !indices.is_empty() && indices.iter().all(|&i| self.recipient(i) == Some(key))
+    }
+
+    /// Fails with `InvalidAuthority` if any of `indices` is bound to a recipient
+    /// other than `key`. Unbound indices are left to the usual checks.
+    pub fn check_recipients(
+        &self,
+        key: &Pubkey,
+        indices: impl IntoIterator<Item = u64>,
+    ) -> ProgramResult {
+        if self.recipients.is_empty() {
+            return Ok(());
+        }
+        for index in indices {
+            if self
+                .recipient(index)
+                .is_some_and(|recipient| recipient != key)
+            {
+                return Err(Error::InvalidAuthority.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Total fee for claiming `count` indices at `fee_per_index` each, so fee-bearing
+/// claims can settle with a single transfer instead of one per index.
+pub fn claim_fee(fee_per_index: u64, count: usize) -> Result<u64, ProgramError> {
+    fee_per_index
+        .checked_mul(count as u64)
+        .ok_or(ProgramError::ArithmeticOverflow)
+}
+
+// --- Instructions ---
+
+#[derive(borsh::BorshSerialize, borsh::BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Create {
+        capacity: u64,
+        redemption_window: u64,
+        close_recipient: [u8; 32],
+        /// Lamports deposited on top of rent, e.g. as a reserve for fee rebates.
+        extra_lamports: u64,
+    },
+    Claim {
+        indices: Vec<u64>,
+    },
+    ClaimExpired {
+        index: u64,
+    },
+    Diagnose,
+    ClaimAll,
+    /// Claims `indices` and then fails. Only accepted with the `debug-ops` feature;
+    /// used to prove a failed transaction leaves no partial claims behind.
+    ClaimThenFail {
+        indices: Vec<u64>,
+    },
+    SetNote {
+        note: String,
+    },
+    /// Returns the program `ID` followed by the crate version string.
+    Ping,
+    /// Like `Create`, but succeeds without changes if the card already exists with
+    /// the same authority and configuration.
+    CreateIdempotent {
+        capacity: u64,
+        redemption_window: u64,
+        close_recipient: [u8; 32],
+    },
+    /// Claims `indices` and then hands the card to `new_authority`, atomically.
+    /// Fails with `Full` if the claim would fill the card.
+    ClaimAndTransfer {
+        indices: Vec<u64>,
+        new_authority: [u8; 32],
+    },
+    /// Claims the `count` lowest unclaimed indices, or as many as remain, up to
+    /// `MAX_CLAIM_INDICES` per instruction.
+    ClaimNext {
+        count: u64,
+    },
+    /// Clears every claim and resizes the card to `new_capacity` in one step,
+    /// for reusing a card in a new round.
+    ResetAndResize {
+        new_capacity: u64,
+    },
+    /// Claims `index` if it is unclaimed and succeeds either way, returning
+    /// whether the claim was new. Useful as a receipt that can always be produced.
+    TouchClaim {
+        index: u64,
+    },
+    /// Claims an unclaimed index picked pseudo-randomly from `seed` and the
+    /// current slot, returning it as a little-endian `u64`.
+    ClaimRandom {
+        seed: u64,
+    },
+    /// Claims the lowest `remaining * bps / 10_000` unclaimed indices, up to
+    /// `MAX_CLAIM_INDICES` per instruction, for drip campaigns releasing a share
+    /// of what is left.
+    ClaimPercent {
+        bps: u16,
+    },
+    /// Deposits `lamports` from the signer into the card, refunded pro rata when
+    /// the card closes. Each deposit must be at least the rent-exempt minimum
+    /// of an empty account.
+    Contribute {
+        lamports: u64,
+    },
+    /// Succeeds only if `index` is claimed. Kept lean and allocation-free so other
+    /// programs can gate on a claim with a cheap CPI.
+    RequireClaimed {
+        index: u64,
+    },
+    /// `Claim` that is only valid up to and including `valid_until_slot`, so a
+    /// signed transaction cannot be replayed later in an unintended context.
+    ClaimUntil {
+        indices: Vec<u64>,
+        valid_until_slot: u64,
+    },
+    /// Like `Create`, but with every index already claimed.
+    CreateInverted {
+        capacity: u64,
+        redemption_window: u64,
+        close_recipient: [u8; 32],
+    },
+    /// Hands the card to `new_authority`, keeping every claim.
+    TransferAuthority {
+        new_authority: [u8; 32],
+    },
+    /// Closes the card whatever its claims, refunding rent like a full card.
+    Close,
+    Unclaim {
+        indices: Vec<u64>,
+    },
+    /// Claims every index in `[start, end)`.
+    ClaimRange {
+        start: u64,
+        end: u64,
+    },
+    /// Repairs `claimed` from the bitset.
+    Recount,
+    /// Like `Create`, but at the PDA `[PUNCHCARD_SEED, payer, seed]`, so the
+    /// punchcard does not sign.
+    CreatePda {
+        capacity: u64,
+        seed: [u8; 32],
+    },
+    /// Grows the card to `new_capacity`, keeping every claim.
+    Resize {
+        new_capacity: u64,
+    },
+    /// Like `Create`, with `name` (up to 64 bytes of UTF-8) as the initial note.
+    CreateNamed {
+        capacity: u64,
+        redemption_window: u64,
+        close_recipient: [u8; 32],
+        name: String,
+    },
+    /// Like `Create`, with claims refused after `expiry_slot` (zero for never).
+    CreateExpiring {
+        capacity: u64,
+        redemption_window: u64,
+        close_recipient: [u8; 32],
+        expiry_slot: u64,
+    },
+    /// Returns the card's authority, capacity and claimed count without
+    /// changing it.
+    Query,
+    /// Like `Create`, with closing reserved to `close_authority` (zero for the
+    /// authority).
+    CreateWithCloseAuthority {
+        capacity: u64,
+        redemption_window: u64,
+        close_recipient: [u8; 32],
+        close_authority: [u8; 32],
+    },
+    /// Freezes (`true`) or resumes (`false`) claims without closing the card.
+    SetPaused {
+        paused: bool,
+    },
+    /// `Claim` on several cards with one authority. Each entry names a card by
+    /// its position among the accounts after the authority.
+    MultiClaim {
+        claims: Vec<(u8, Vec<u64>)>,
+    },
+    /// Lets `delegate` claim on the card alongside the authority.
+    AddDelegate {
+        delegate: [u8; 32],
+    },
+    /// Revokes a delegate added with `AddDelegate`.
+    RemoveDelegate {
+        delegate: [u8; 32],
+    },
+    /// Claims `index` for any signer whose `proof` shows it is in the card's
+    /// `merkle_root`.
+    ClaimWithProof {
+        index: u64,
+        proof: Vec<[u8; 32]>,
+    },
+    /// Sets the root `ClaimWithProof` checks against (zero to disable it).
+    SetMerkleRoot {
+        merkle_root: [u8; 32],
+    },
+    /// Claims `index` for any signer, given an earlier Ed25519 program
+    /// instruction in which `signer_pubkey` signs `claim_message`.
+    ClaimSigned {
+        index: u64,
+    },
+    /// Sets the key `ClaimSigned` checks for (zero to disable it).
+    SetClaimSigner {
+        signer_pubkey: [u8; 32],
+    },
+    /// `Claim`, with a separate payer paying the authority `fee` lamports per
+    /// index claimed.
+    ClaimForFee {
+        indices: Vec<u64>,
+        fee: u64,
+    },
+    /// Flips each of `indices` between claimed and unclaimed, for tooling that
+    /// corrects cards by hand.
+    Toggle {
+        indices: Vec<u64>,
+    },
+    /// Like `Create`, with room to bind each index to a recipient, who is then
+    /// the only key that may claim it.
+    CreateWithRecipients {
+        capacity: u64,
+        redemption_window: u64,
+        close_recipient: [u8; 32],
+    },
+    /// Binds `recipients` to the indices from `start` on (zero to unbind).
+    SetRecipients {
+        start: u64,
+        recipients: Vec<[u8; 32]>,
+    },
+    /// Rewrites a card from the unversioned legacy layout in the current one,
+    /// keeping its claims. The authority pays for the larger header.
+    Migrate,
+}
+
+// --- Errors ---
+
+// Error codes are part of the program's interface: they are append-only, and a
+// code is never renumbered or reused once released. Codes 0..=2 were released
+// before the ranges below were drawn and keep their values; every later variant
+// takes the next free code in the range for its category.
+
+/// Card state and indexing: bounds, claims, layout.
+pub const ERROR_RANGE_STATE: core::ops::Range<u32> = 0..32;
+/// Signers, authorities and the accounts an instruction is routed to.
+pub const ERROR_RANGE_AUTH: core::ops::Range<u32> = 32..64;
+/// Lamports: rent, deposits and refunds.
+pub const ERROR_RANGE_FUNDS: core::ops::Range<u32> = 64..96;
+/// Runtime limits such as compute and sizes.
+pub const ERROR_RANGE_LIMITS: core::ops::Range<u32> = 96..128;
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    InvalidAuthority = 0,
+    IndexOutOfBounds = 1,
+    AlreadyClaimed = 2,
+    NotClaimed = 3,
+    ChecksumMismatch = 4,
+    InvalidCapacity = 5,
+    AlreadyInitialized = 6,
+    Full = 7,
+    InvalidCloseRecipient = 8,
+    InsufficientComputeBudget = 9,
+    NotRentExempt = 10,
+    BadMagic = 11,
+    TooManyContributors = 12,
+    InvalidContributor = 13,
+    PunchcardIsPda = 14,
+    DuplicateIndex = 15,
+    CannotShrink = 16,
+    UnsupportedVersion = 17,
+    Expired = 18,
+    Paused = 19,
+    ClaimExpired = 20,
+    InvalidProof = 32,
+    InvalidClaimSignature = 33,
+    ContributionTooSmall = 64,
+    TooManyIndices = 96,
+    TooManyDelegates = 97,
+}
+
+impl Error {
+    /// Same as `ProgramError::from(self)`.
+    pub fn into_program_error(self) -> ProgramError {
+        self.into()
+    }
+
+    /// Human-readable description, also used by `Display`.
+    pub fn message(&self) -> &'static str {
+        match self {
+            Error::InvalidAuthority => "signer does not match the punchcard authority",
+            Error::IndexOutOfBounds => "index out of bounds",
+            Error::AlreadyClaimed => "index already claimed",
+            Error::NotClaimed => "index not claimed",
+            Error::ChecksumMismatch => "header checksum mismatch",
+            Error::InvalidCapacity => "invalid capacity",
+            Error::AlreadyInitialized => "punchcard already exists with a different configuration",
+            Error::Full => "punchcard is full",
+            Error::InvalidCloseRecipient => "close recipient does not match the header",
+            Error::InsufficientComputeBudget => "insufficient compute budget for the claim",
+            Error::NotRentExempt => "punchcard is not rent exempt",
+            Error::BadMagic => "account is not a punchcard",
+            Error::TooManyContributors => "too many contributors",
+            Error::InvalidContributor => "contributor account does not match the header",
+            Error::PunchcardIsPda => "punchcard is a PDA that did not sign",
+            Error::DuplicateIndex => "index repeated within one claim",
+            Error::CannotShrink => "new capacity is below the current capacity",
+            Error::UnsupportedVersion => "punchcard layout version is not supported",
+            Error::Expired => "punchcard has expired",
+            Error::Paused => "punchcard is paused",
+            Error::TooManyIndices => "too many indices in one claim",
+            Error::ClaimExpired => "claim arrived after its valid_until_slot",
+            Error::InvalidProof => "merkle proof does not match the card's root",
+            Error::InvalidClaimSignature => "no Ed25519 instruction signs this claim",
+            Error::ContributionTooSmall => "contribution is below the minimum",
+            Error::TooManyDelegates => "too many delegates",
+        }
+    }
+}
+
+impl From<Error> for ProgramError {
+    fn from(error: Error) -> Self {
+        ProgramError::Custom(error as u32)
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+// --- Processor ---
+
+#[cfg(not(feature = "no-entrypoint"))]
+entrypoint!(process);
+
+pub fn decode_instruction(data: &[u8]) -> Result<Instruction, ProgramError> {
+    borsh::from_slice(data).map_err(|_| ProgramError::InvalidInstructionData)
+}
+
+pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    match decode_instruction(data)? {
+        Instruction::Create {
+            capacity,
+            redemption_window,
+            close_recipient,
+            extra_lamports,
+        } => {
+            let config =
+                CreateConfig::new(capacity, redemption_window, close_recipient, extra_lamports)?;
+            create(program_id, accounts, &config)
+        }
+        Instruction::Claim { indices } => {
+            // A successful `Claim` always sets at least one bit. The derived claims
+            // (`ClaimNext`, `ClaimPercent`, ...) may still find nothing to claim.
+            if indices.is_empty() {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            claim(program_id, accounts, &indices)
+        }
+        Instruction::ClaimExpired { index } => claim_expired(program_id, accounts, index),
+        Instruction::Diagnose => diagnose(program_id, accounts),
+        Instruction::ClaimAll => claim_all(program_id, accounts),
+        #[cfg(feature = "debug-ops")]
+        Instruction::ClaimThenFail { indices } => {
+            claim(program_id, accounts, &indices)?;
+            Err(ProgramError::InvalidArgument)
+        }
+        #[cfg(not(feature = "debug-ops"))]
+        Instruction::ClaimThenFail { .. } => Err(ProgramError::InvalidInstructionData),
+        Instruction::SetNote { note } => set_note(program_id, accounts, &note),
+        Instruction::Ping => ping(),
+        Instruction::CreateIdempotent {
+            capacity,
+            redemption_window,
+            close_recipient,
+        } => {
+            let config = CreateConfig::new(capacity, redemption_window, close_recipient, 0)?;
+            create_idempotent(program_id, accounts, &config)
+        }
+        Instruction::ClaimAndTransfer {
+            indices,
+            new_authority,
+        } => claim_and_transfer(program_id, accounts, &indices, new_authority),
+        Instruction::ClaimNext { count } => claim_next(program_id, accounts, count),
+        Instruction::ResetAndResize { new_capacity } => {
+            let new_capacity = Capacity::new(new_capacity)?;
+            reset_and_resize(program_id, accounts, new_capacity)
+        }
+        Instruction::TouchClaim { index } => touch_claim(program_id, accounts, index),
+        Instruction::ClaimRandom { seed } => claim_random(program_id, accounts, seed),
+        Instruction::ClaimPercent { bps } => claim_percent(program_id, accounts, bps),
+        Instruction::Contribute { lamports } => contribute(program_id, accounts, lamports),
+        Instruction::RequireClaimed { index } => require_claimed(program_id, accounts, index),
+        Instruction::ClaimUntil {
+            indices,
+            valid_until_slot,
+        } => {
+            if pinocchio::sysvars::clock::Clock::get()?.slot > valid_until_slot {
+                return Err(Error::ClaimExpired.into());
+            }
+            claim(program_id, accounts, &indices)
+        }
+        Instruction::CreateInverted {
+            capacity,
+            redemption_window,
+            close_recipient,
+        } => {
+            let mut config = CreateConfig::new(capacity, redemption_window, close_recipient, 0)?;
+            config.initial_fill = InitialFill::Full;
+            create(program_id, accounts, &config)
+        }
+        Instruction::TransferAuthority { new_authority } => {
+            transfer_authority(program_id, accounts, new_authority)
+        }
+        Instruction::Close => close(program_id, accounts),
+        Instruction::Unclaim { indices } => unclaim(program_id, accounts, &indices),
+        Instruction::ClaimRange { start, end } => claim_range(program_id, accounts, start, end),
+        Instruction::Recount => recount(program_id, accounts),
+        Instruction::CreatePda { capacity, seed } => {
+            let mut config = CreateConfig::new(capacity, 0, [0; 32], 0)?;
+            config.seed = Some(seed);
+            create(program_id, accounts, &config)
+        }
+        Instruction::CreateNamed {
+            capacity,
+            redemption_window,
+            close_recipient,
+            name,
+        } => {
+            let mut config = CreateConfig::new(capacity, redemption_window, close_recipient, 0)?;
+            config.note = note_bytes(&name)?;
+            create(program_id, accounts, &config)
+        }
+        Instruction::CreateExpiring {
+            capacity,
+            redemption_window,
+            close_recipient,
+            expiry_slot,
+        } => {
+            let mut config = CreateConfig::new(capacity, redemption_window, close_recipient, 0)?;
+            config.expiry_slot = expiry_slot;
+            create(program_id, accounts, &config)
+        }
+        Instruction::Resize { new_capacity } => {
+            let new_capacity = Capacity::new(new_capacity)?;
+            resize(program_id, accounts, new_capacity)
+        }
+        Instruction::Query => query(program_id, accounts),
+        Instruction::CreateWithCloseAuthority {
+            capacity,
+            redemption_window,
+            close_recipient,
+            close_authority,
+        } => {
+            let mut config = CreateConfig::new(capacity, redemption_window, close_recipient, 0)?;
+            config.close_authority = close_authority;
+            create(program_id, accounts, &config)
+        }
+        Instruction::SetPaused { paused } => set_paused(program_id, accounts, paused),
+        Instruction::MultiClaim { claims } => {
+            // Held to `Claim`'s rule for every card
+            if claims.is_empty() || claims.iter().any(|(_, indices)| indices.is_empty()) {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            multi_claim(program_id, accounts, &claims)
+        }
+        Instruction::AddDelegate { delegate } => add_delegate(program_id, accounts, delegate),
+        Instruction::RemoveDelegate { delegate } => remove_delegate(program_id, accounts, delegate),
+        Instruction::ClaimWithProof { index, proof } => {
+            claim_with_proof(program_id, accounts, index, &proof)
+        }
+        Instruction::SetMerkleRoot { merkle_root } => {
+            set_merkle_root(program_id, accounts, merkle_root)
+        }
+        Instruction::ClaimSigned { index } => claim_signed(program_id, accounts, index),
+        Instruction::SetClaimSigner { signer_pubkey } => {
+            set_claim_signer(program_id, accounts, signer_pubkey)
+        }
+        Instruction::ClaimForFee { indices, fee } => {
+            if indices.is_empty() {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            claim_for_fee(program_id, accounts, &indices, fee)
+        }
+        Instruction::Toggle { indices } => toggle(program_id, accounts, &indices),
+        Instruction::CreateWithRecipients {
+            capacity,
+            redemption_window,
+            close_recipient,
+        } => {
+            let mut config = CreateConfig::new(capacity, redemption_window, close_recipient, 0)?;
+            config.bind_recipients = true;
+            create(program_id, accounts, &config)
+        }
+        Instruction::SetRecipients { start, recipients } => {
+            set_recipients(program_id, accounts, start, &recipients)
+        }
+        Instruction::Migrate => migrate(program_id, accounts),
+    }
+}
+
+/// Validated settings shared by the create instructions.
+struct CreateConfig {
+    capacity: Capacity,
+    redemption_window: u64,
+    close_recipient: Pubkey,
+    extra_lamports: u64,
+    initial_fill: InitialFill,
+    /// Creates the card at `[PUNCHCARD_SEED, payer, seed]` instead of a keypair.
+    seed: Option<[u8; 32]>,
+    note: [u8; 64],
+    expiry_slot: u64,
+    close_authority: Pubkey,
+    bind_recipients: bool,
+}
+
+impl CreateConfig {
+    fn new(
+        capacity: u64,
+        redemption_window: u64,
+        close_recipient: Pubkey,
+        extra_lamports: u64,
+    ) -> Result<Self, ProgramError> {
+        Ok(Self {
+            capacity: Capacity::new(capacity)?,
+            redemption_window,
+            close_recipient,
+            extra_lamports,
+            initial_fill: InitialFill::Empty,
+            seed: None,
+            note: [0; 64],
+            expiry_slot: 0,
+            close_authority: [0; 32],
+            bind_recipients: false,
+        })
+    }
+
+    /// Whether an existing card was created with these settings by `authority`.
+    fn matches(&self, header: &PunchcardHeader, authority: &Pubkey) -> bool {
+        header.authority == *authority
+            && header.capacity == self.capacity.get()
+            && header.redemption_window == self.redemption_window
+            && header.close_recipient == self.close_recipient
+    }
+}
+
+fn create(program_id: &Pubkey, accounts: &[AccountInfo], config: &CreateConfig) -> ProgramResult {
+    let [payer, punchcard, system] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if system.key() != &pinocchio_system::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let bump = match &config.seed {
+        Some(seed) => {
+            let (address, bump) = pinocchio::pubkey::try_find_program_address(
+                &[PUNCHCARD_SEED, &payer.key()[..], &seed[..]],
+                program_id,
+            )
+            .ok_or(ProgramError::InvalidSeeds)?;
+            if address != *punchcard.key() {
+                return Err(ProgramError::InvalidSeeds);
+            }
+            bump
+        }
+        None => {
+            // A PDA can only sign through `invoke_signed` by its owning program; name the
+            // mistake instead of letting `CreateAccount` fail on a missing signature
+            if !punchcard.is_signer() && !is_on_curve(punchcard.key()) {
+                return Err(Error::PunchcardIsPda.into());
+            }
+            0
+        }
+    };
+    // Never clobber a live card, and fail the same way for accounts funded up front
+    // rather than leaving it to whatever `CreateAccount` makes of them
+    if punchcard.lamports() != 0 || punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let capacity = config.capacity;
+    let redemption_window = config.redemption_window;
+    let space = Punchcard::account_len(capacity.get(), redemption_window, config.bind_recipients)?;
+    let rent = pinocchio::sysvars::rent::Rent::get()?.minimum_balance(space);
+    // The reserve is funded by the same transfer as rent, so it is all or nothing
+    let lamports = rent
+        .checked_add(config.extra_lamports)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    if payer.lamports() < lamports {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    let create_account = CreateAccount {
+        from: payer,
+        to: punchcard,
+        lamports,
+        space: space as u64,
+        owner: program_id,
+    };
+    match &config.seed {
+        Some(seed) => {
+            let bump = [bump];
+            let seeds = [
+                Seed::from(PUNCHCARD_SEED),
+                Seed::from(&payer.key()[..]),
+                Seed::from(&seed[..]),
+                Seed::from(&bump[..]),
+            ];
+            create_account.invoke_signed(&[Signer::from(&seeds[..])])?;
+        }
+        None => create_account.invoke()?,
+    }
+
+    if punchcard.lamports() < rent {
+        return Err(Error::NotRentExempt.into());
+    }
+
+    let header = PunchcardHeader {
+        authority: *payer.key(),
+        capacity: capacity.get(),
+        created_slot: pinocchio::sysvars::clock::Clock::get()?.slot,
+        redemption_window,
+        close_recipient: config.close_recipient,
+        bump,
+        note: config.note,
+        expiry_slot: config.expiry_slot,
+        close_authority: config.close_authority,
+        bind_recipients: config.bind_recipients as u8,
+        ..bytemuck::Zeroable::zeroed()
+    };
+    let mut data = punchcard.try_borrow_mut_data()?;
+    Punchcard::initialize(&mut data, header, config.initial_fill)?;
+
+    Ok(())
+}
+
+/// Whether `key` is an ed25519 point, i.e. could belong to a keypair. PDAs are
+/// off the curve by construction.
+fn is_on_curve(key: &Pubkey) -> bool {
+    #[cfg(target_os = "solana")]
+    {
+        const CURVE25519_EDWARDS: u64 = 0;
+        let mut result = 0u8;
+        unsafe {
+            pinocchio::syscalls::sol_curve_validate_point(
+                CURVE25519_EDWARDS,
+                key.as_ptr(),
+                &mut result,
+            ) == 0
+        }
+    }
+    #[cfg(not(target_os = "solana"))]
+    {
+        let _ = key;
+        true
+    }
+}
+
+fn create_idempotent(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: &CreateConfig,
+) -> ProgramResult {
+    let [payer, punchcard, _system] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if punchcard.is_owned_by(program_id) && !punchcard.data_is_empty() {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let card = Punchcard::from_bytes(&mut data)?;
+
+        if config.matches(card.header, payer.key()) {
+            return Ok(());
+        }
+        return Err(Error::AlreadyInitialized.into());
+    }
+
+    create(program_id, accounts, config)
+}
+
+fn claim(program_id: &Pubkey, accounts: &[AccountInfo], indices: &[u64]) -> ProgramResult {
+    let [authority, punchcard, remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    claim_card(program_id, authority, punchcard, remaining, indices)
+}
+
+/// `Claim` on one card, with `remaining` holding the accounts its close needs.
+fn claim_card(
+    program_id: &Pubkey,
+    authority: &AccountInfo,
+    punchcard: &AccountInfo,
+    remaining: &[AccountInfo],
+    indices: &[u64],
+) -> ProgramResult {
+    claim_authorized(
+        program_id,
+        authority,
+        punchcard,
+        remaining,
+        indices,
+        |card| {
+            let key = authority.key();
+            if !card.header.can_claim(key) && !card.bound_to(key, indices) {
+                return Err(Error::InvalidAuthority.into());
+            }
+            Ok(())
+        },
+    )
+}
+
+/// Claims `indices` for `claimer` once `authorize` accepts the card, closing the
+/// card if that fills it and its closer signed. Whatever `authorize` allows, an
+/// index bound to a recipient can only be claimed by that recipient.
+fn claim_authorized(
+    program_id: &Pubkey,
+    claimer: &AccountInfo,
+    punchcard: &AccountInfo,
+    remaining: &[AccountInfo],
+    indices: &[u64],
+    authorize: impl FnOnce(&Punchcard) -> ProgramResult,
+) -> ProgramResult {
+    check_index_count(indices.len() as u64)?;
+    if !claimer.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // Fail here rather than with the runtime's read-only write error afterwards.
+    // A rent recipient that cannot be credited is caught in `close_punchcard`.
+    if !punchcard.is_writable() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (full, closer, close_recipient, contributors) = {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let mut card = Punchcard::from_bytes(&mut data)?;
+
+        authorize(&card)?;
+        card.check_recipients(claimer.key(), indices.iter().copied())?;
+
+        let slot = claim_slot(card.header)?;
+
+        #[cfg(feature = "compute-guard")]
+        check_compute_budget(indices.len())?;
+
+        card.claim_many(indices)?;
+        for &i in indices {
+            card.stamp(i, slot);
+        }
+        card.header.update_checksum();
+        // Set while the header is still readable; a full card may be closed below
+        pinocchio::cpi::set_return_data(&return_data::encode_claim(
+            card.header.claimed,
+            card.header.capacity,
+        ));
+
+        (
+            card.is_full(),
+            *card.header.closer(),
+            card.header.close_recipient,
+            card.header.contributors,
+        )
+    };
+
+    let closer = if full {
+        close_signer(&closer, claimer, remaining)
+    } else {
+        None
+    };
+    if let Some((closer, remaining)) = closer {
+        let (recipient, remaining) = rent_recipient(&close_recipient, closer, remaining)?;
+        close_punchcard(punchcard, recipient, &contributors, remaining)?;
+    }
+
+    log_claim(indices.len() as u64, closer.is_some());
+
+    Ok(())
+}
+
+fn multi_claim(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    claims: &[(u8, Vec<u64>)],
+) -> ProgramResult {
+    let [authority, punchcards @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    for (card, indices) in claims {
+        let punchcard = punchcards
+            .get(*card as usize)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        // There is nowhere to pass a card's close recipient or contributors, so
+        // filling a card that needs them fails with `NotEnoughAccountKeys`
+        claim_card(program_id, authority, punchcard, &[], indices)?;
+    }
+
+    Ok(())
+}
+
+fn claim_range(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    start: u64,
+    end: u64,
+) -> ProgramResult {
+    let [authority, punchcard, remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    // A reversed range is left to `Punchcard::claim_range` to reject
+    check_index_count(end.saturating_sub(start))?;
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !punchcard.is_writable() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (full, closer, close_recipient, contributors) = {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let mut card = Punchcard::from_bytes(&mut data)?;
+
+        if !card.header.can_claim(authority.key()) {
+            return Err(Error::InvalidAuthority.into());
+        }
+        card.check_recipients(authority.key(), start..end)?;
+
+        let slot = claim_slot(card.header)?;
+        card.claim_range(start, end)?;
+        if card.header.redemption_window != 0 {
+            for i in start..end {
+                card.stamp(i, slot);
+            }
+        }
+        card.header.update_checksum();
+
+        (
+            card.is_full(),
+            *card.header.closer(),
+            card.header.close_recipient,
+            card.header.contributors,
+        )
+    };
+
+    let closer = if full {
+        close_signer(&closer, authority, remaining)
+    } else {
+        None
+    };
+    if let Some((closer, remaining)) = closer {
+        let (recipient, remaining) = rent_recipient(&close_recipient, closer, remaining)?;
+        close_punchcard(punchcard, recipient, &contributors, remaining)?;
+    }
+
+    log_claim(end - start, closer.is_some());
+
+    Ok(())
+}
+
+fn claim_with_proof(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    index: u64,
+    proof: &[[u8; 32]],
+) -> ProgramResult {
+    let [claimer, punchcard, remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    // The proof stands in for the authority check
+    claim_authorized(
+        program_id,
+        claimer,
+        punchcard,
+        remaining,
+        &[index],
+        |card| {
+            let root = card.header.merkle_root;
+            if root == [0; 32] || !merkle::verify(&root, index, proof) {
+                return Err(Error::InvalidProof.into());
+            }
+            Ok(())
+        },
+    )
+}
+
+fn claim_for_fee(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    indices: &[u64],
+    fee: u64,
+) -> ProgramResult {
+    let [authority, punchcard, payer, _system, remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !payer.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !payer.is_writable() || !authority.is_writable() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let total = claim_fee(fee, indices.len())?;
+
+    // Paid up front so the claim's return data is the last thing set
+    Transfer {
+        from: payer,
+        to: authority,
+        lamports: total,
+    }
+    .invoke()?;
+
+    claim_authorized(
+        program_id,
+        authority,
+        punchcard,
+        remaining,
+        indices,
+        |card| {
+            // The fee is the authority's, so a delegate cannot collect it
+            if card.header.authority != *authority.key() {
+                return Err(Error::InvalidAuthority.into());
+            }
+            Ok(())
+        },
+    )
+}
+
+fn claim_signed(program_id: &Pubkey, accounts: &[AccountInfo], index: u64) -> ProgramResult {
+    let [claimer, punchcard, instructions, remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let message = claim_message(punchcard.key(), index);
+    claim_authorized(
+        program_id,
+        claimer,
+        punchcard,
+        remaining,
+        &[index],
+        |card| {
+            let signer = card.header.signer_pubkey;
+            if signer == [0; 32] || !ed25519_verified(instructions, &signer, &message)? {
+                return Err(Error::InvalidClaimSignature.into());
+            }
+            Ok(())
+        },
+    )
+}
+
+fn recount(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let [authority, punchcard, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    // The counter being repaired may well exceed capacity
+    let mut card = Punchcard::from_bytes_unchecked_counts(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    card.recount();
+    card.header.update_checksum();
+
+    Ok(())
+}
+
+fn unclaim(program_id: &Pubkey, accounts: &[AccountInfo], indices: &[u64]) -> ProgramResult {
+    check_index_count(indices.len() as u64)?;
+    let [authority, punchcard, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let mut card = Punchcard::from_bytes(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    for &i in indices {
+        card.unclaim(i)?;
+    }
+    card.header.update_checksum();
+
+    Ok(())
+}
+
+fn toggle(program_id: &Pubkey, accounts: &[AccountInfo], indices: &[u64]) -> ProgramResult {
+    check_index_count(indices.len() as u64)?;
+    let [authority, punchcard, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let mut card = Punchcard::from_bytes(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    for &i in indices {
+        card.toggle(i)?;
+    }
+    card.header.update_checksum();
+
+    Ok(())
+}
+
+fn touch_claim(program_id: &Pubkey, accounts: &[AccountInfo], index: u64) -> ProgramResult {
+    let [authority, punchcard, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let already_claimed = {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let card = Punchcard::from_bytes(&mut data)?;
+
+        // The same rule as `Claim`: a bound recipient may touch its own index,
+        // and nobody else may touch it
+        let key = authority.key();
+        if !card.header.can_claim(key) && !card.bound_to(key, &[index]) {
+            return Err(Error::InvalidAuthority.into());
+        }
+        card.check_recipients(key, [index])?;
+        card.is_claimed(index)?
+    };
+
+    if !already_claimed {
+        claim(program_id, accounts, &[index])?;
+    }
+    pinocchio::cpi::set_return_data(&return_data::encode_touch_claim(!already_claimed));
+
+    Ok(())
+}
+
+fn claim_random(program_id: &Pubkey, accounts: &[AccountInfo], seed: u64) -> ProgramResult {
+    let [_authority, punchcard, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let index = {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let card = Punchcard::from_bytes(&mut data)?;
+
+        let slot = pinocchio::sysvars::clock::Clock::get()?.slot;
+        let hash = keccak(&[&seed.to_le_bytes()[..], &slot.to_le_bytes()[..]]);
+        let offset = u64::from_le_bytes(hash[..8].try_into().unwrap());
+        // Scanning forward from the offset always finds a free index if one remains
+        card.next_unclaimed_from(offset).ok_or(Error::Full)?
+    };
+
+    claim(program_id, accounts, &[index])?;
+    pinocchio::cpi::set_return_data(&return_data::encode_claim_random(index));
+
+    Ok(())
+}
+
+fn claim_percent(program_id: &Pubkey, accounts: &[AccountInfo], bps: u16) -> ProgramResult {
+    let [_authority, punchcard, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if bps > 10_000 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let indices = {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let card = Punchcard::from_bytes(&mut data)?;
+
+        let count = card
+            .remaining()
+            .checked_mul(bps as u64)
+            .ok_or(ProgramError::ArithmeticOverflow)?
+            / 10_000;
+        card.next_unclaimed(count.min(MAX_CLAIM_INDICES as u64) as usize)
+    };
+
+    claim(program_id, accounts, &indices)?;
+    pinocchio::cpi::set_return_data(&return_data::encode_claim_percent(&indices));
+
+    Ok(())
+}
+
+fn claim_and_transfer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    indices: &[u64],
+    new_authority: Pubkey,
+) -> ProgramResult {
+    let [authority, punchcard, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if new_authority == [0; 32] {
+        return Err(Error::InvalidAuthority.into());
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let card = Punchcard::from_bytes(&mut data)?;
+        // A claim that fills the card closes it, leaving nothing to hand over, so
+        // it is refused up front rather than dropping `new_authority`
+        if indices.len() as u64 >= card.remaining() {
+            return Err(Error::Full.into());
+        }
+    }
+
+    claim(program_id, accounts, indices)?;
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let card = Punchcard::from_bytes(&mut data)?;
+    // A delegate may claim but not hand the card over
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+    card.header.authority = new_authority;
+    card.header.update_checksum();
+
+    Ok(())
+}
+
+fn claim_next(program_id: &Pubkey, accounts: &[AccountInfo], count: u64) -> ProgramResult {
+    let [_authority, punchcard, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let indices = {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let card = Punchcard::from_bytes(&mut data)?;
+        card.next_unclaimed(count.min(MAX_CLAIM_INDICES as u64) as usize)
+    };
+
+    claim(program_id, accounts, &indices)
+}
+
+fn claim_all(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let [authority, punchcard, remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !punchcard.is_writable() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (newly_claimed, closer, close_recipient, contributors) = {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let mut card = Punchcard::from_bytes(&mut data)?;
+
+        if !card.header.can_claim(authority.key()) {
+            return Err(Error::InvalidAuthority.into());
+        }
+        check_index_count(card.remaining())?;
+        let unclaimed = card.bits.iter_unclaimed(card.header.capacity);
+        card.check_recipients(authority.key(), unclaimed)?;
+
+        let slot = claim_slot(card.header)?;
+        let newly_claimed = card.claim_all(slot)?;
+        card.header.update_checksum();
+        (
+            newly_claimed,
+            *card.header.closer(),
+            card.header.close_recipient,
+            card.header.contributors,
+        )
+    };
+
+    let closer = close_signer(&closer, authority, remaining);
+    if let Some((closer, remaining)) = closer {
+        let (recipient, remaining) = rent_recipient(&close_recipient, closer, remaining)?;
+        close_punchcard(punchcard, recipient, &contributors, remaining)?;
+    }
+    log_claim(newly_claimed, closer.is_some());
+
+    Ok(())
+}
+
+fn transfer_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_authority: Pubkey,
+) -> ProgramResult {
+    let [authority, punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if new_authority == [0; 32] {
+        return Err(Error::InvalidAuthority.into());
+    }
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let card = Punchcard::from_bytes(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    card.header.authority = new_authority;
+    card.header.update_checksum();
+
+    Ok(())
+}
+
+fn close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let [authority, punchcard, remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let (close_recipient, contributors) = {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let card = Punchcard::from_bytes(&mut data)?;
+
+        if card.header.closer() != authority.key() {
+            return Err(Error::InvalidAuthority.into());
+        }
+        (card.header.close_recipient, card.header.contributors)
+    };
+
+    let (recipient, remaining) = rent_recipient(&close_recipient, authority, remaining)?;
+    close_punchcard(punchcard, recipient, &contributors, remaining)
+}
+
+fn set_note(program_id: &Pubkey, accounts: &[AccountInfo], note: &str) -> ProgramResult {
+    let [authority, punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let note = note_bytes(note)?;
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let card = Punchcard::from_bytes(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    card.header.note = note;
+
+    Ok(())
+}
+
+fn set_paused(program_id: &Pubkey, accounts: &[AccountInfo], paused: bool) -> ProgramResult {
+    let [authority, punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let card = Punchcard::from_bytes(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    card.header.paused = paused as u8;
+
+    Ok(())
+}
+
+fn add_delegate(program_id: &Pubkey, accounts: &[AccountInfo], delegate: Pubkey) -> ProgramResult {
+    let [authority, punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if delegate == [0; 32] {
+        return Err(Error::InvalidAuthority.into());
+    }
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let card = Punchcard::from_bytes(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    if card.header.delegates.contains(&delegate) {
+        return Ok(());
+    }
+    *card
+        .header
+        .delegates
+        .iter_mut()
+        .find(|d| **d == [0; 32])
+        .ok_or(Error::TooManyDelegates)? = delegate;
+
+    Ok(())
+}
+
+fn remove_delegate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    delegate: Pubkey,
+) -> ProgramResult {
+    let [authority, punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let card = Punchcard::from_bytes(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    for entry in card.header.delegates.iter_mut() {
+        if *entry == delegate {
+            *entry = [0; 32];
+        }
+    }
+
+    Ok(())
+}
+
+fn set_merkle_root(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    merkle_root: [u8; 32],
+) -> ProgramResult {
+    let [authority, punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let card = Punchcard::from_bytes(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    card.header.merkle_root = merkle_root;
+
+    Ok(())
+}
+
+fn set_claim_signer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signer_pubkey: Pubkey,
+) -> ProgramResult {
+    let [authority, punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let card = Punchcard::from_bytes(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    card.header.signer_pubkey = signer_pubkey;
+
+    Ok(())
+}
+
+fn set_recipients(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    start: u64,
+    recipients: &[Pubkey],
+) -> ProgramResult {
+    let [authority, punchcard, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let mut card = Punchcard::from_bytes(&mut data)?;
+
+    if card.header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    card.bind_recipients(start, recipients)
+}
+
+/// Reads a card in the legacy layout as a current header. `None` for a card
+/// already in the current layout.
+fn migrated_header(data: &[u8]) -> Result<Option<PunchcardHeader>, ProgramError> {
+    if data.get(..8) == Some(&PUNCHCARD_MAGIC[..]) {
+        let version = *data
+            .get(core::mem::offset_of!(PunchcardHeader, version))
+            .ok_or(ProgramError::AccountDataTooSmall)?;
+        if version != CURRENT_VERSION {
+            return Err(Error::UnsupportedVersion.into());
+        }
+        return Ok(None);
+    }
+
+    let old = data.get(..LEGACY_HEADER_LEN).ok_or(Error::BadMagic)?;
+    let mut header = PunchcardHeader {
+        magic: PUNCHCARD_MAGIC,
+        capacity: bytemuck::pod_read_unaligned(&old[32..40]),
+        claimed: bytemuck::pod_read_unaligned(&old[40..48]),
+        version: CURRENT_VERSION,
+        ..bytemuck::Zeroable::zeroed()
+    };
+    header.authority.copy_from_slice(&old[..32]);
+
+    // The legacy body is the bitset alone
+    let body = Punchcard::account_len(header.capacity, 0, false)? - PUNCHCARD_HEADER_LEN;
+    if data.len() != LEGACY_HEADER_LEN + body {
+        return Err(Error::BadMagic.into());
+    }
+
+    header.update_checksum();
+    Ok(Some(header))
+}
+
+fn migrate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let [authority, punchcard, _system] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let migrated = {
+        let data = punchcard.try_borrow_data()?;
+        migrated_header(&data)?
+    };
+    let Some(header) = migrated else {
+        return Ok(());
+    };
+    if header.authority != *authority.key() {
+        return Err(Error::InvalidAuthority.into());
+    }
+
+    let space = Punchcard::account_len(header.capacity, header.redemption_window, false)?;
+    let rent = pinocchio::sysvars::rent::Rent::get()?.minimum_balance(space);
+
+    punchcard.resize(space)?;
+
+    let lamports = punchcard.lamports();
+    if lamports < rent {
+        Transfer {
+            from: authority,
+            to: punchcard,
+            lamports: rent - lamports,
+        }
+        .invoke()?;
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    // The legacy header is shorter, so the body only moves up
+    data.copy_within(
+        LEGACY_HEADER_LEN..LEGACY_HEADER_LEN + space - PUNCHCARD_HEADER_LEN,
+        PUNCHCARD_HEADER_LEN,
+    );
+    data[..PUNCHCARD_HEADER_LEN].copy_from_slice(bytemuck::bytes_of(&header));
+
+    // Legacy cards kept their own count, which nothing checked
+    let mut card = Punchcard::from_bytes_unchecked_counts(&mut data)?;
+    card.recount();
+    card.header.total_claims = card.header.total_claims.max(card.header.claimed);
+    card.header.update_checksum();
+
+    Ok(())
+}
+
+/// `note` zero-padded to the header's note field.
+fn note_bytes(note: &str) -> Result<[u8; 64], ProgramError> {
+    let mut bytes = [0; 64];
+    bytes
+        .get_mut(..note.len())
+        .ok_or(ProgramError::InvalidInstructionData)?
+        .copy_from_slice(note.as_bytes());
+    Ok(bytes)
+}
+
+fn reset_and_resize(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_capacity: Capacity,
+) -> ProgramResult {
+    let [authority, punchcard, _system] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let (redemption_window, bound, deposits) = {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let card = Punchcard::from_bytes(&mut data)?;
+
+        if card.header.authority != *authority.key() {
+            return Err(Error::InvalidAuthority.into());
+        }
+        let deposits = card
+            .header
+            .contributors
+            .iter()
+            .try_fold(0u64, |sum, c| sum.checked_add(c.lamports))
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        (
+            card.header.redemption_window,
+            card.header.binds_recipients(),
+            deposits,
+        )
+    };
+
+    let space = Punchcard::account_len(new_capacity.get(), redemption_window, bound)?;
+    let rent = pinocchio::sysvars::rent::Rent::get()?.minimum_balance(space);
+    // Contributors stay recorded, so their deposits stay on the card for the
+    // refund when it closes
+    let keep = rent
+        .checked_add(deposits)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    punchcard.resize(space)?;
+
+    let lamports = punchcard.lamports();
+    if lamports < rent {
+        Transfer {
+            from: authority,
+            to: punchcard,
+            lamports: rent - lamports,
+        }
+        .invoke()?;
+    } else if lamports > keep {
+        let mut authority_lamports = authority.try_borrow_mut_lamports()?;
+        *authority_lamports = refund(*authority_lamports, lamports - keep)?;
+        drop(authority_lamports);
+        *punchcard.try_borrow_mut_lamports()? = keep;
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let (header, rest) = data.split_at_mut(size_of::<PunchcardHeader>());
+    // Bits, stamps and recipients all start over; none survives a resize meaningfully
+    rest.fill(0);
+    let header: &mut PunchcardHeader = bytemuck::from_bytes_mut(header);
+    header.capacity = new_capacity.get();
+    header.claimed = 0;
+    header.update_checksum();
+
+    Ok(())
+}
+
+fn resize(program_id: &Pubkey, accounts: &[AccountInfo], new_capacity: Capacity) -> ProgramResult {
+    let [authority, punchcard, _system] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let (capacity, redemption_window, bound) = {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let card = Punchcard::from_bytes(&mut data)?;
+
+        if card.header.authority != *authority.key() {
+            return Err(Error::InvalidAuthority.into());
+        }
+        if new_capacity.get() < card.header.capacity {
+            return Err(Error::CannotShrink.into());
+        }
+        (
+            card.header.capacity,
+            card.header.redemption_window,
+            card.header.binds_recipients(),
+        )
+    };
+
+    let space = Punchcard::account_len(new_capacity.get(), redemption_window, bound)?;
+    let rent = pinocchio::sysvars::rent::Rent::get()?.minimum_balance(space);
+
+    punchcard.resize(space)?;
+
+    let lamports = punchcard.lamports();
+    if lamports < rent {
+        Transfer {
+            from: authority,
+            to: punchcard,
+            lamports: rent - lamports,
+        }
+        .invoke()?;
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let (header, rest) = data.split_at_mut(size_of::<PunchcardHeader>());
+    // Stamps follow the bitset and recipients follow the stamps, so each moves up
+    // by however much the regions before it grew. Recipients go first, as they
+    // move furthest.
+    let old_bits = capacity.div_ceil(8) as usize;
+    let new_bits = new_capacity.get().div_ceil(8) as usize;
+    let stamp_width = if redemption_window != 0 { 2 } else { 0 };
+    let old_stamps = capacity as usize * stamp_width;
+    let new_stamps = new_capacity.get() as usize * stamp_width;
+    let recipients = if bound { capacity as usize * 32 } else { 0 };
+    let recipients_at = old_bits + old_stamps;
+    rest.copy_within(
+        recipients_at..recipients_at + recipients,
+        new_bits + new_stamps,
+    );
+    rest.copy_within(old_bits..old_bits + old_stamps, new_bits);
+    rest[old_bits..new_bits].fill(0);
+    rest[new_bits + old_stamps..new_bits + new_stamps].fill(0);
+    rest[new_bits + new_stamps + recipients..].fill(0);
+
+    let header: &mut PunchcardHeader = bytemuck::from_bytes_mut(header);
+    header.capacity = new_capacity.get();
+    header.update_checksum();
+
+    Ok(())
+}
+
+fn contribute(program_id: &Pubkey, accounts: &[AccountInfo], lamports: u64) -> ProgramResult {
+    let [contributor, punchcard, _system] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if lamports == 0 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if !contributor.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // Keeps dust from taking up the few contributor entries, and makes every
+    // refund large enough to land in an account that has since been emptied
+    if lamports < pinocchio::sysvars::rent::Rent::get()?.minimum_balance(0) {
+        return Err(Error::ContributionTooSmall.into());
+    }
+
+    {
+        let mut data = punchcard.try_borrow_mut_data()?;
+        let card = Punchcard::from_bytes(&mut data)?;
+
+        let contributors = &mut card.header.contributors;
+        let entry = match contributors
+            .iter()
+            .position(|c| c.key == *contributor.key())
+        {
+            Some(i) => &mut contributors[i],
+            None => contributors
+                .iter_mut()
+                .find(|c| c.key == [0; 32])
+                .ok_or(Error::TooManyContributors)?,
+        };
+        entry.key = *contributor.key();
+        entry.lamports = entry
+            .lamports
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+    }
+
+    Transfer {
+        from: contributor,
+        to: punchcard,
+        lamports,
+    }
+    .invoke()
+}
+
+fn ping() -> ProgramResult {
+    pinocchio::cpi::set_return_data(&return_data::encode_ping());
+    Ok(())
+}
+
+/// Most indices one instruction may claim, unclaim or toggle on a card. Batches
+/// the program picks itself, as `ClaimNext` and `ClaimPercent` do, are cut to
+/// this many rather than refused.
+pub const MAX_CLAIM_INDICES: usize = 256;
+
+/// Conservative per-index cost of a claim, including stamping and the close
+/// that may follow.
+#[cfg(feature = "compute-guard")]
+const CLAIM_INDEX_COMPUTE_UNITS: u64 = 100;
+
+/// Logs `punchcard: claimed=N closed=B` for indexers that parse program logs.
+fn log_claim(count: u64, closed: bool) {
+    let mut buf = [0; 64];
+    pinocchio::log::sol_log(format_claim_log(count, closed, &mut buf));
+}
+
+/// Formats the `log_claim` line into `buf`, since the program does not allocate
+/// for logs.
+fn format_claim_log(count: u64, closed: bool, buf: &mut [u8; 64]) -> &str {
+    const PREFIX: &[u8] = b"punchcard: claimed=";
+    let mut digits = [0u8; 20];
+    let mut start = digits.len();
+    let mut n = count;
+    loop {
+        start -= 1;
+        digits[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    let suffix: &[u8] = if closed {
+        b" closed=true"
+    } else {
+        b" closed=false"
+    };
+
+    let mut len = 0;
+    for part in [PREFIX, &digits[start..], suffix] {
+        buf[len..len + part.len()].copy_from_slice(part);
+        len += part.len();
+    }
+    core::str::from_utf8(&buf[..len]).unwrap_or_default()
+}
+
+/// The slot to stamp claims on `header` with, or zero if it keeps no stamps.
+/// Fails with `Paused` while the card is paused, and with `Expired` once its
+/// `expiry_slot` has passed.
+fn claim_slot(header: &PunchcardHeader) -> Result<u64, ProgramError> {
+    if header.is_paused() {
+        return Err(Error::Paused.into());
+    }
+    if header.redemption_window == 0 && header.expiry_slot == 0 {
+        return Ok(0);
+    }
+    let slot = pinocchio::sysvars::clock::Clock::get()?.slot;
+    if header.is_expired(slot) {
+        return Err(Error::Expired.into());
+    }
+    Ok(slot)
+}
+
+/// The message a card's `signer_pubkey` signs to authorize `ClaimSigned` on
+/// `index`: the punchcard's key followed by the index as a little-endian `u64`.
+pub fn claim_message(punchcard: &Pubkey, index: u64) -> [u8; 40] {
+    let mut message = [0; 40];
+    message[..32].copy_from_slice(punchcard);
+    message[32..].copy_from_slice(&index.to_le_bytes());
+    message
+}
+
+/// Whether an Ed25519 program instruction before the current one verified
+/// `signer`'s signature over `message`.
+fn ed25519_verified(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> Result<bool, ProgramError> {
+    let instructions = pinocchio::sysvars::instructions::Instructions::try_from(instructions)?;
+    for i in 0..instructions.load_current_index() as usize {
+        let instruction = instructions.load_instruction_at(i)?;
+        if *instruction.get_program_id() == ED25519_PROGRAM_ID
+            && ed25519_signs(instruction.get_instruction_data(), signer, message)
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Whether Ed25519 program instruction `data` covers `signer` signing `message`.
+/// Only entries whose signature, key and message all sit in `data` itself count,
+/// so an entry cannot borrow its bytes from another instruction.
+fn ed25519_signs(data: &[u8], signer: &Pubkey, message: &[u8]) -> bool {
+    // A count and a padding byte, then seven `u16`s per signature: signature
+    // offset and instruction, key offset and instruction, message offset, size
+    // and instruction. `u16::MAX` means this instruction.
+    const ENTRY_LEN: usize = 14;
+    let Some(&count) = data.first() else {
+        return false;
+    };
+    (0..count as usize).any(|entry| {
+        let Some(entry) = data.get(2 + entry * ENTRY_LEN..2 + (entry + 1) * ENTRY_LEN) else {
+            return false;
+        };
+        let field = |i: usize| u16::from_le_bytes([entry[2 * i], entry[2 * i + 1]]) as usize;
+        let here = u16::MAX as usize;
+        if field(1) != here || field(3) != here || field(6) != here {
+            return false;
+        }
+        data.get(field(2)..field(2) + 32) == Some(&signer[..])
+            && data.get(field(4)..field(4) + field(5)) == Some(message)
+    })
+}
+
+/// Fails with `TooManyIndices` if one instruction would touch more than
+/// `MAX_CLAIM_INDICES` indices.
+fn check_index_count(count: u64) -> ProgramResult {
+    if count > MAX_CLAIM_INDICES as u64 {
+        return Err(Error::TooManyIndices.into());
+    }
+    Ok(())
+}
+
+/// Fails with `InsufficientComputeBudget` when the remaining compute units are
+/// unlikely to cover claiming `count` indices, rather than running out mid-loop.
+#[cfg(feature = "compute-guard")]
+fn check_compute_budget(count: usize) -> ProgramResult {
+    #[cfg(target_os = "solana")]
+    let remaining = unsafe { pinocchio::syscalls::sol_remaining_compute_units() };
+    #[cfg(not(target_os = "solana"))]
+    let remaining = u64::MAX;
+
+    if (count as u64).saturating_mul(CLAIM_INDEX_COMPUTE_UNITS) > remaining {
+        return Err(Error::InsufficientComputeBudget.into());
+    }
+    Ok(())
+}
+
+/// The account a closing card's rent goes to: the configured `close_recipient`,
+/// which must then be passed right after the punchcard, or else the authority.
+/// Also returns the accounts after it.
+fn rent_recipient<'b>(
+    close_recipient: &Pubkey,
+    authority: &'b AccountInfo,
+    remaining: &'b [AccountInfo],
+) -> Result<(&'b AccountInfo, &'b [AccountInfo]), ProgramError> {
+    if *close_recipient == [0; 32] {
+        return Ok((authority, remaining));
+    }
+    let [recipient, rest @ ..] = remaining else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    if recipient.key() != close_recipient {
+        return Err(Error::InvalidCloseRecipient.into());
+    }
+    Ok((recipient, rest))
+}
+
+/// The signer closing a card that just filled, which is also the default rent
+/// recipient, and the accounts after it. The card closes with the claim if its
+/// `closer` made it. Otherwise, as for a delegate's claim or a card with a
+/// `close_authority`, the closer must be passed, signing, right after the
+/// punchcard; without it the card stays open, full, until `Close`.
+fn close_signer<'b>(
+    closer: &Pubkey,
+    claimer: &'b AccountInfo,
+    remaining: &'b [AccountInfo],
+) -> Option<(&'b AccountInfo, &'b [AccountInfo])> {
+    if claimer.key() == closer {
+        return Some((claimer, remaining));
+    }
+    match remaining {
+        [signer, rest @ ..] if signer.key() == closer && signer.is_signer() => Some((signer, rest)),
+        _ => None,
+    }
+}
+
+/// Closes the card, refunding each contributor their share and everything else
+/// to `recipient`. Contributor accounts are expected in `contributor_accounts`
+/// in the order they appear in the header. A share the runtime would refuse to
+/// credit, too small to make an emptied account rent exempt, goes to
+/// `recipient` instead of failing the close.
+fn close_punchcard(
+    punchcard: &AccountInfo,
+    recipient: &AccountInfo,
+    contributors: &[Contribution; MAX_CONTRIBUTORS],
+    contributor_accounts: &[AccountInfo],
+) -> ProgramResult {
+    if !recipient.is_writable() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (refunds, mut rest) = split_refund(punchcard.lamports(), contributors);
+    let mut accounts = contributor_accounts.iter();
+    for (contribution, amount) in contributors.iter().zip(refunds) {
+        if contribution.key == [0; 32] {
+            continue;
+        }
+        let account = accounts.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if *account.key() != contribution.key {
+            return Err(Error::InvalidContributor.into());
+        }
+        if !account.is_writable() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if account.lamports() == 0
+            && amount < pinocchio::sysvars::rent::Rent::get()?.minimum_balance(0)
+        {
+            rest = refund(rest, amount)?;
+            continue;
+        }
+        let mut lamports = account.try_borrow_mut_lamports()?;
+        *lamports = refund(*lamports, amount)?;
+    }
+
+    let mut recipient_lamports = recipient.try_borrow_mut_lamports()?;
+    *recipient_lamports = refund(*recipient_lamports, rest)?;
+    drop(recipient_lamports);
+    *punchcard.try_borrow_mut_lamports()? = 0;
+    punchcard.try_borrow_mut_data()?.fill(0);
+    punchcard.close()?;
+
+    if punchcard.lamports() != 0 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(())
+}
+
+/// Balance of a close recipient after receiving `amount`, rejecting overflow
+/// instead of wrapping.
+fn refund(balance: u64, amount: u64) -> Result<u64, ProgramError> {
+    balance
+        .checked_add(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)
+}
+
+fn claim_expired(program_id: &Pubkey, accounts: &[AccountInfo], index: u64) -> ProgramResult {
+    let [punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let card = Punchcard::from_bytes(&mut data)?;
+
+    if !card.is_claimed(index)? {
+        return Err(Error::NotClaimed.into());
+    }
+
+    let slot = pinocchio::sysvars::clock::Clock::get()?.slot;
+    let expired = card.claim_expired(index, slot);
+    pinocchio::cpi::set_return_data(&return_data::encode_claim_expired(expired));
+
+    Ok(())
+}
+
+fn require_claimed(program_id: &Pubkey, accounts: &[AccountInfo], index: u64) -> ProgramResult {
+    let [punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Reads only the fields it needs instead of validating the whole card
+    let data = punchcard.try_borrow_data()?;
+    if data.len() < size_of::<PunchcardHeader>() {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    if data[..8] != PUNCHCARD_MAGIC {
+        return Err(Error::BadMagic.into());
+    }
+    if data[core::mem::offset_of!(PunchcardHeader, version)] != CURRENT_VERSION {
+        return Err(Error::UnsupportedVersion.into());
+    }
+    let capacity: u64 = bytemuck::pod_read_unaligned(&data[CAPACITY_OFFSET..CAPACITY_OFFSET + 8]);
+
+    if index >= capacity {
+        return Err(Error::IndexOutOfBounds.into());
+    }
+    let byte = data
+        .get(BITS_OFFSET + (index / 8) as usize)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    if byte & (1 << (index % 8)) == 0 {
+        return Err(Error::NotClaimed.into());
+    }
+
+    Ok(())
+}
+
+fn diagnose(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let [punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let mut failures = 0;
+    if !punchcard.is_owned_by(program_id) {
+        failures |= DIAGNOSE_BAD_OWNER;
+    }
+
+    let data = punchcard.try_borrow_data()?;
+    let version = data.get(core::mem::offset_of!(PunchcardHeader, version));
+    // The rest of the header only means anything in the current layout, so a card
+    // every other instruction would refuse goes no further
+    if data.get(..8) != Some(&PUNCHCARD_MAGIC[..]) {
+        failures |= DIAGNOSE_BAD_MAGIC;
+    } else if version.is_some_and(|&version| version != CURRENT_VERSION) {
+        failures |= DIAGNOSE_BAD_VERSION;
+    } else if data.len() < size_of::<PunchcardHeader>() {
+        failures |= DIAGNOSE_BAD_HEADER_SIZE;
+    } else {
+        let (header, rest) = data.split_at(size_of::<PunchcardHeader>());
+        let header: PunchcardHeader = bytemuck::pod_read_unaligned(header);
+
+        if header.claimed > header.capacity {
+            failures |= DIAGNOSE_CLAIMED_OVER_CAPACITY;
+        }
+        if header.checksum != header.compute_checksum() {
+            failures |= DIAGNOSE_CHECKSUM_MISMATCH;
+        }
+
+        let len = Punchcard::account_len(
+            header.capacity,
+            header.redemption_window,
+            header.binds_recipients(),
+        );
+        if len != Ok(data.len()) {
+            failures |= DIAGNOSE_BAD_BITSET_LEN;
+        } else {
+            // Popcount is only meaningful once the bitset is known to be the right size
+            let bits_len = header.capacity.div_ceil(8) as usize;
+            let mut popcount = 0u64;
+            for (i, &byte) in rest[..bits_len].iter().enumerate() {
+                let valid = header.capacity - i as u64 * 8;
+                let mask = if valid >= 8 { 0xFF } else { (1u8 << valid) - 1 };
+                popcount += (byte & mask).count_ones() as u64;
+            }
+            if popcount != header.claimed {
+                failures |= DIAGNOSE_POPCOUNT_MISMATCH;
+            }
+        }
+    }
+
+    pinocchio::cpi::set_return_data(&return_data::encode_diagnose(failures));
+
+    Ok(())
+}
+
+fn query(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let [punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !punchcard.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = punchcard.try_borrow_mut_data()?;
+    let card = Punchcard::from_bytes(&mut data)?;
+    pinocchio::cpi::set_return_data(&return_data::encode_query(card.header));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A current-version card over `bits`, with `claimed` and the checksum
+    /// kept consistent. Shared with the client tests.
+    pub(crate) fn card_bytes(capacity: u64, bits: &[u8]) -> Vec<u8> {
+        let mut header = PunchcardHeader {
+            magic: PUNCHCARD_MAGIC,
+            authority: [1; 32],
+            capacity,
+            claimed: bits.iter().map(|b| b.count_ones() as u64).sum(),
+            version: CURRENT_VERSION,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        header.update_checksum();
+        let mut data = bytemuck::bytes_of(&header).to_vec();
+        data.extend_from_slice(bits);
+        data
+    }
+
+    #[test]
+    fn test_claimed_merkle_root() {
+        // Claimed: 1, 4, 9
+        let mut data = card_bytes(16, &[0b0001_0010, 0b0000_0010]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+
+        let left = merkle::parent(&merkle::leaf(1), &merkle::leaf(4));
+        let root = card.claimed_merkle_root();
+        assert_eq!(root, merkle::parent(&left, &merkle::leaf(9)));
+
+        // Proofs against the root check out, including for the node carried up
+        assert!(merkle::verify(
+            &root,
+            1,
+            &[merkle::leaf(4), merkle::leaf(9)]
+        ));
+        assert!(merkle::verify(
+            &root,
+            4,
+            &[merkle::leaf(1), merkle::leaf(9)]
+        ));
+        assert!(merkle::verify(&root, 9, &[left]));
+        assert!(!merkle::verify(
+            &root,
+            2,
+            &[merkle::leaf(4), merkle::leaf(9)]
+        ));
+
+        let mut data = card_bytes(16, &[0, 0]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+        assert_eq!(card.claimed_merkle_root(), [0; 32]);
+    }
+
+    #[test]
+    fn test_unclaimed_in_range_crosses_bytes() {
+        // Claimed: 1, 3, 5, 7, 8..=11, 20..=23
+        let mut data = card_bytes(24, &[0b1010_1010, 0b0000_1111, 0b1111_0000]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+
+        // [3, 21) holds 3, 5, 7, 8, 9, 10, 11, 20 claimed out of 18 slots
+        assert_eq!(card.bits.count_ones_in_range(3, 21), 8);
+        assert_eq!(card.unclaimed_in_range(3, 21).unwrap(), 10);
+        assert_eq!(card.unclaimed_in_range(0, 24).unwrap(), 12);
+        assert_eq!(card.unclaimed_in_range(12, 12).unwrap(), 0);
+        assert!(card.unclaimed_in_range(0, 25).is_err());
+        assert!(card.unclaimed_in_range(5, 4).is_err());
+    }
+
+    #[test]
+    fn test_claimed_in_range_masks_boundary_bytes() {
+        // Claimed: 1, 3, 5, 7, 8..=11, 20..=23
+        let mut data = card_bytes(24, &[0b1010_1010, 0b0000_1111, 0b1111_0000]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+
+        for (start, end, expected) in [
+            (0, 24, 12),
+            (3, 21, 8),
+            (2, 7, 2),
+            (4, 4, 0),
+            (7, 9, 2),
+            (9, 22, 5),
+            (12, 20, 0),
+            (23, 24, 1),
+        ] {
+            assert_eq!(
+                card.claimed_in_range(start, end).unwrap(),
+                expected,
+                "[{start}, {end})"
+            );
+            assert_eq!(
+                card.claimed_in_range(start, end).unwrap()
+                    + card.unclaimed_in_range(start, end).unwrap(),
+                end - start
+            );
+        }
+        assert!(card.claimed_in_range(0, 25).is_err());
+        assert!(card.claimed_in_range(5, 4).is_err());
+    }
+
+    #[test]
+    fn test_split_refund() {
+        let contribution = |key, lamports| Contribution {
+            key: [key; 32],
+            lamports,
+        };
+        let mut contributors = [Contribution::default(); MAX_CONTRIBUTORS];
+        contributors[0] = contribution(1, 1_000);
+        contributors[2] = contribution(2, 3_000);
+
+        // Everyone is made whole and the rest goes to the recipient
+        assert_eq!(
+            split_refund(10_000, &contributors),
+            ([1_000, 0, 3_000, 0], 6_000)
+        );
+        // A balance short of the contributions is shared pro rata, with the dust
+        // left over for the recipient
+        assert_eq!(split_refund(3_001, &contributors), ([750, 0, 2_250, 0], 1));
+        assert_eq!(
+            split_refund(5_000, &[Contribution::default(); MAX_CONTRIBUTORS]),
+            ([0; MAX_CONTRIBUTORS], 5_000)
+        );
+    }
+
+    #[test]
+    fn test_layout_offsets() {
+        use core::mem::offset_of;
+
+        assert_eq!(PUNCHCARD_HEADER_LEN, 576);
+        assert_eq!(AUTHORITY_OFFSET, offset_of!(PunchcardHeader, authority));
+        assert_eq!(CAPACITY_OFFSET, offset_of!(PunchcardHeader, capacity));
+        assert_eq!(CLAIMED_OFFSET, offset_of!(PunchcardHeader, claimed));
+        assert_eq!(BITS_OFFSET, size_of::<PunchcardHeader>());
+    }
+
+    #[test]
+    fn test_recipients_gate_their_indices() {
+        let mut data = card_bytes(4, &[0]);
+        data[core::mem::offset_of!(PunchcardHeader, bind_recipients)] = 1;
+        data.resize(Punchcard::account_len(4, 0, true).unwrap(), 0);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+        assert_eq!(card.recipients.len(), 4 * 32);
+
+        let (alice, bob) = ([7; 32], [8; 32]);
+        card.bind_recipients(1, &[alice, bob]).unwrap();
+        assert_eq!(card.recipient(0), None);
+        assert_eq!(card.recipient(1), Some(&alice));
+        assert_eq!(card.recipient(2), Some(&bob));
+        assert_eq!(card.recipient(4), None);
+
+        assert!(card.bound_to(&alice, &[1]));
+        assert!(!card.bound_to(&alice, &[1, 2]));
+        assert!(!card.bound_to(&alice, &[]));
+        assert_eq!(card.check_recipients(&alice, [0, 1, 3]), Ok(()));
+        assert_eq!(
+            card.check_recipients(&alice, [1, 2]),
+            Err(ProgramError::Custom(0))
+        );
+
+        assert_eq!(
+            card.bind_recipients(3, &[alice, bob]),
+            Err(ProgramError::Custom(1))
+        );
+        card.bind_recipients(2, &[[0; 32]]).unwrap();
+        assert_eq!(card.recipient(2), None);
+
+        let mut data = card_bytes(4, &[0]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+        assert!(card.recipients.is_empty());
+        assert_eq!(
+            card.bind_recipients(0, &[alice]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(card.check_recipients(&bob, [0]), Ok(()));
+    }
+
+    #[test]
+    fn test_space_overflow() {
+        let header = size_of::<PunchcardHeader>();
+        assert_eq!(Punchcard::space(u64::MAX), Ok(header + (1 << 61)));
+        assert_eq!(Punchcard::space(u64::MAX - 7), Ok(header + (1 << 61) - 1));
+        for capacity in [u64::MAX, u64::MAX - 7] {
+            assert_eq!(
+                Punchcard::stamped_space(capacity),
+                Err(Error::InvalidCapacity.into())
+            );
+            assert_eq!(
+                Punchcard::account_len(capacity, 10, false),
+                Err(ProgramError::Custom(5))
+            );
+        }
+    }
+
+    #[test]
+    fn test_lengths_do_not_depend_on_pointer_width() {
+        let boundaries = [0, 1, 7, 8, 9]
+            .into_iter()
+            .chain(u32::MAX as u64 - 8..=u32::MAX as u64 + 8)
+            .chain(u64::MAX / 2 - 8..=u64::MAX / 2 + 8)
+            .chain(u64::MAX - 16..=u64::MAX);
+        let header = PUNCHCARD_HEADER_LEN as u128;
+        for capacity in boundaries {
+            let bits = (capacity as u128).div_ceil(8);
+            let narrow = |len: u128| usize::try_from(len).ok();
+            assert_eq!(Punchcard::bitset_len(capacity), narrow(bits));
+            assert_eq!(Punchcard::space(capacity).ok(), narrow(header + bits));
+            assert_eq!(
+                Punchcard::stamped_space(capacity).ok(),
+                narrow(header + bits + 2 * capacity as u128),
+                "capacity {capacity}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_initialize_fills() {
+        let header = PunchcardHeader {
+            authority: [1; 32],
+            capacity: 13,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        let len = Punchcard::space(13).unwrap();
+
+        let mut data = vec![0xAA; len];
+        let card = Punchcard::initialize(&mut data, header, InitialFill::Empty).unwrap();
+        assert_eq!(card.header.claimed, 0);
+        assert_eq!(card.header.magic, PUNCHCARD_MAGIC);
+        assert_eq!(card.bits.count_ones(), 0);
+
+        let mut data = vec![0xAA; len];
+        let card = Punchcard::initialize(&mut data, header, InitialFill::Full).unwrap();
+        assert_eq!(card.header.claimed, 13);
+        assert_eq!(card.bits.count_ones(), 13);
+        // Padding above index 12 stays clear even over dirty data
+        assert_eq!(data[len - 1], 0b0001_1111);
+        assert!(Punchcard::from_bytes(&mut data).is_ok());
+    }
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        // Clients match on these numbers; changing one is a breaking change
+        let code = |error: Error| match error {
+            Error::InvalidAuthority => 0,
+            Error::IndexOutOfBounds => 1,
+            Error::AlreadyClaimed => 2,
+            Error::NotClaimed => 3,
+            Error::ChecksumMismatch => 4,
+            Error::InvalidCapacity => 5,
+            Error::AlreadyInitialized => 6,
+            Error::Full => 7,
+            Error::InvalidCloseRecipient => 8,
+            Error::InsufficientComputeBudget => 9,
+            Error::NotRentExempt => 10,
+            Error::BadMagic => 11,
+            Error::TooManyContributors => 12,
+            Error::InvalidContributor => 13,
+            Error::PunchcardIsPda => 14,
+            Error::DuplicateIndex => 15,
+            Error::CannotShrink => 16,
+            Error::UnsupportedVersion => 17,
+            Error::Expired => 18,
+            Error::Paused => 19,
+            Error::ClaimExpired => 20,
+            Error::InvalidProof => 32,
+            Error::InvalidClaimSignature => 33,
+            Error::ContributionTooSmall => 64,
+            Error::TooManyIndices => 96,
+            Error::TooManyDelegates => 97,
+        };
+        let errors = [
+            Error::InvalidAuthority,
+            Error::IndexOutOfBounds,
+            Error::AlreadyClaimed,
+            Error::NotClaimed,
+            Error::ChecksumMismatch,
+            Error::InvalidCapacity,
+            Error::AlreadyInitialized,
+            Error::Full,
+            Error::InvalidCloseRecipient,
+            Error::InsufficientComputeBudget,
+            Error::NotRentExempt,
+            Error::BadMagic,
+            Error::TooManyContributors,
+            Error::InvalidContributor,
+            Error::PunchcardIsPda,
+            Error::DuplicateIndex,
+            Error::CannotShrink,
+            Error::UnsupportedVersion,
+            Error::Expired,
+            Error::Paused,
+            Error::ClaimExpired,
+            Error::InvalidProof,
+            Error::InvalidClaimSignature,
+            Error::ContributionTooSmall,
+            Error::TooManyIndices,
+            Error::TooManyDelegates,
+        ];
+        for error in errors {
+            assert_eq!(error as u32, code(error), "{error:?} was renumbered");
+            assert_eq!(ProgramError::from(error), ProgramError::Custom(code(error)));
+            assert_eq!(error.into_program_error(), ProgramError::from(error));
+        }
+
+        let ranges = [
+            ERROR_RANGE_STATE,
+            ERROR_RANGE_AUTH,
+            ERROR_RANGE_FUNDS,
+            ERROR_RANGE_LIMITS,
+        ];
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+        // Both kinds of expiry describe the card's state, not who signed
+        for error in [Error::Expired, Error::ClaimExpired] {
+            assert!(ERROR_RANGE_STATE.contains(&(error as u32)), "{error:?}");
+        }
+        // A bad proof or signature is refused authorization, like a wrong signer
+        for error in [Error::InvalidProof, Error::InvalidClaimSignature] {
+            assert!(ERROR_RANGE_AUTH.contains(&(error as u32)), "{error:?}");
+        }
+        // Caps on how much a card records, like the cap on indices per claim
+        for error in [Error::TooManyIndices, Error::TooManyDelegates] {
+            assert!(ERROR_RANGE_LIMITS.contains(&(error as u32)), "{error:?}");
+        }
+    }
+
+    #[test]
+    fn test_unclaim_and_toggle_reject_too_many_indices() {
+        // Checked before any account is looked at. Claims check in
+        // `claim_authorized`, which needs real accounts; see the integration tests.
+        let instructions: [fn(Vec<u64>) -> Instruction; 2] = [
+            |indices| Instruction::Unclaim { indices },
+            |indices| Instruction::Toggle { indices },
+        ];
+        for instruction in instructions {
+            let data =
+                borsh::to_vec(&instruction((0..=MAX_CLAIM_INDICES as u64).collect())).unwrap();
+            assert_eq!(process(&ID, &[], &data), Err(Error::TooManyIndices.into()));
+            let data =
+                borsh::to_vec(&instruction((0..MAX_CLAIM_INDICES as u64).collect())).unwrap();
+            assert_eq!(
+                process(&ID, &[], &data),
+                Err(ProgramError::NotEnoughAccountKeys)
+            );
+        }
+    }
+
+    #[test]
+    fn test_debug_summarizes_card() {
+        let mut data = card_bytes(13, &[0b0000_0101, 0]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+        card.header.authority = ID;
+
+        let header = format!("{:?}", card.header);
+        assert!(header.contains("capacity: 13"), "{header}");
+        assert!(header.contains("claimed: 2"), "{header}");
+        assert!(
+            header.contains("authority: pcWKVSdcdDUKabPz4pVfaQ2jMod1kWv3LqeQivjKXiF"),
+            "{header}"
+        );
+
+        let card = format!("{card:?}");
+        assert!(
+            card.starts_with("Punchcard { header: PunchcardHeader {"),
+            "{card}"
+        );
+        assert!(
+            card.ends_with("claimed_bits: 2, stamped: false, bound: false }"),
+            "{card}"
+        );
+
+        assert_eq!(format!("{:?}", Base58(&[0; 32])), "1".repeat(32));
+    }
+
+    #[test]
+    fn test_claim_log_format() {
+        let mut buf = [0; 64];
+        assert_eq!(
+            format_claim_log(0, false, &mut buf),
+            "punchcard: claimed=0 closed=false"
+        );
+        assert_eq!(
+            format_claim_log(1207, true, &mut buf),
+            "punchcard: claimed=1207 closed=true"
+        );
+        assert_eq!(
+            format_claim_log(u64::MAX, false, &mut buf),
+            "punchcard: claimed=18446744073709551615 closed=false"
+        );
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let messages = [
+            (
+                Error::InvalidAuthority,
+                "signer does not match the punchcard authority",
+            ),
+            (Error::IndexOutOfBounds, "index out of bounds"),
+            (Error::AlreadyClaimed, "index already claimed"),
+            (Error::NotClaimed, "index not claimed"),
+            (Error::ChecksumMismatch, "header checksum mismatch"),
+            (Error::InvalidCapacity, "invalid capacity"),
+            (
+                Error::AlreadyInitialized,
+                "punchcard already exists with a different configuration",
+            ),
+            (Error::Full, "punchcard is full"),
+            (
+                Error::InvalidCloseRecipient,
+                "close recipient does not match the header",
+            ),
+            (
+                Error::InsufficientComputeBudget,
+                "insufficient compute budget for the claim",
+            ),
+            (Error::NotRentExempt, "punchcard is not rent exempt"),
+            (Error::BadMagic, "account is not a punchcard"),
+            (Error::TooManyContributors, "too many contributors"),
+            (
+                Error::InvalidContributor,
+                "contributor account does not match the header",
+            ),
+            (
+                Error::PunchcardIsPda,
+                "punchcard is a PDA that did not sign",
+            ),
+            (Error::DuplicateIndex, "index repeated within one claim"),
+            (
+                Error::CannotShrink,
+                "new capacity is below the current capacity",
+            ),
+            (
+                Error::UnsupportedVersion,
+                "punchcard layout version is not supported",
+            ),
+            (Error::Expired, "punchcard has expired"),
+            (Error::Paused, "punchcard is paused"),
+            (
+                Error::ClaimExpired,
+                "claim arrived after its valid_until_slot",
+            ),
+            (
+                Error::InvalidProof,
+                "merkle proof does not match the card's root",
+            ),
+            (
+                Error::InvalidClaimSignature,
+                "no Ed25519 instruction signs this claim",
+            ),
+            (
+                Error::ContributionTooSmall,
+                "contribution is below the minimum",
+            ),
+            (Error::TooManyIndices, "too many indices in one claim"),
+            (Error::TooManyDelegates, "too many delegates"),
+        ];
+        for (error, message) in messages {
+            assert_eq!(error.message(), message);
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_bad_magic() {
+        let mut data = card_bytes(16, &[0, 0]);
+        data[0] = b'X';
+        assert!(matches!(
+            Punchcard::from_bytes(&mut data),
+            Err(ProgramError::Custom(11))
+        ));
+    }
+
+    /// Ed25519 program data for one signature, laid out as the runtime's own
+    /// builder does: offsets, key, signature, message.
+    fn ed25519_data(signer: &Pubkey, message: &[u8], key_instruction: u16) -> Vec<u8> {
+        let here = u16::MAX;
+        let offsets = [
+            48,
+            here,
+            16,
+            key_instruction,
+            112,
+            message.len() as u16,
+            here,
+        ];
+        let mut data = vec![1, 0];
+        for offset in offsets {
+            data.extend_from_slice(&offset.to_le_bytes());
+        }
+        data.extend_from_slice(signer);
+        data.extend_from_slice(&[0; 64]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    #[test]
+    fn test_ed25519_signs_checks_key_and_message() {
+        let signer = [7; 32];
+        let message = claim_message(&[1; 32], 5);
+        let data = ed25519_data(&signer, &message, u16::MAX);
+
+        assert!(ed25519_signs(&data, &signer, &message));
+        assert!(!ed25519_signs(&data, &[8; 32], &message));
+        assert!(!ed25519_signs(&data, &signer, &claim_message(&[1; 32], 6)));
+        // A key read from some other instruction proves nothing
+        assert!(!ed25519_signs(
+            &ed25519_data(&signer, &message, 0),
+            &signer,
+            &message
+        ));
+        assert!(!ed25519_signs(&data[..20], &signer, &message));
+    }
+
+    #[test]
+    fn test_merkle_proof_is_order_independent() {
+        let root = merkle::parent(&merkle::leaf(4), &merkle::leaf(7));
+        assert_eq!(root, merkle::parent(&merkle::leaf(7), &merkle::leaf(4)));
+
+        assert!(merkle::verify(&root, 4, &[merkle::leaf(7)]));
+        assert!(merkle::verify(&root, 7, &[merkle::leaf(4)]));
+        assert!(!merkle::verify(&root, 5, &[merkle::leaf(7)]));
+        assert!(!merkle::verify(&root, 4, &[]));
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_unknown_version() {
+        let version_at = core::mem::offset_of!(PunchcardHeader, version);
+
+        let mut data = card_bytes(16, &[0, 0]);
+        assert!(Punchcard::from_bytes(&mut data).is_ok());
+
+        // Any other version is refused rather than misread
+        for version in [0, CURRENT_VERSION - 1, CURRENT_VERSION + 1] {
+            data[version_at] = version;
+            assert!(matches!(
+                Punchcard::from_bytes(&mut data),
+                Err(ProgramError::Custom(17))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_migrated_header() {
+        let current = card_bytes(20, &[0b0000_0101, 0, 0]);
+        assert!(matches!(migrated_header(&current), Ok(None)));
+
+        // The unversioned layout: authority, capacity, claimed, bits
+        let mut legacy = [7; 32].to_vec();
+        legacy.extend_from_slice(&20u64.to_le_bytes());
+        legacy.extend_from_slice(&2u64.to_le_bytes());
+        legacy.extend_from_slice(&[0b0000_0101, 0, 0]);
+        let header = migrated_header(&legacy).unwrap().unwrap();
+        assert_eq!(header.magic, PUNCHCARD_MAGIC);
+        assert_eq!(header.version, CURRENT_VERSION);
+        assert_eq!(header.authority, [7; 32]);
+        assert_eq!((header.capacity, header.claimed), (20, 2));
+        assert_eq!(header.checksum, header.compute_checksum());
+        // Too short or too long for its capacity is not a card at all
+        assert!(matches!(
+            migrated_header(&legacy[..legacy.len() - 1]),
+            Err(ProgramError::Custom(11))
+        ));
+
+        // Only the legacy layout is migrated; other versions are not cards it knows
+        let mut other = current.clone();
+        other[core::mem::offset_of!(PunchcardHeader, version)] = CURRENT_VERSION + 1;
+        assert!(matches!(
+            migrated_header(&other),
+            Err(ProgramError::Custom(17))
+        ));
+    }
+
+    #[test]
+    fn test_checksum_rejects_tampered_header() {
+        let mut data = card_bytes(16, &[0b0000_0011, 0]);
+        assert!(Punchcard::from_bytes(&mut data).is_ok());
+
+        // Flip a capacity byte without refreshing the checksum
+        data[40] ^= 0x01;
+        assert!(matches!(
+            Punchcard::from_bytes(&mut data),
+            Err(ProgramError::Custom(4))
+        ));
+    }
+
+    #[test]
+    fn test_note_str_trims_padding() {
+        let mut data = card_bytes(8, &[0]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+        assert_eq!(card.header.note_str(), "");
+
+        card.header.note[..5].copy_from_slice(b"hello");
+        assert_eq!(card.header.note_str(), "hello");
+    }
+
+    /// Number of `Instruction` variants: the first discriminant that fails to
+    /// decode even when every field after it is zero, which all field types accept.
+    fn instruction_variant_count() -> u8 {
+        (0..=u8::MAX)
+            .find(|&discriminant| {
+                let mut data = [0u8; 256];
+                data[0] = discriminant;
+                <Instruction as borsh::BorshDeserialize>::deserialize(&mut &data[..]).is_err()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decode_instruction_never_panics() {
+        let variants = instruction_variant_count();
+        #[cfg(feature = "client")]
+        assert_eq!(variants as usize, client::instruction_schema().len());
+
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..10_000 {
+            let len = (next() % 96) as usize;
+            let mut data: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            // Bias towards known discriminants, and the first unknown one, so
+            // every variant body gets exercised as variants are added
+            if let Some(first) = data.first_mut() {
+                *first %= variants + 1;
+            }
+            let _ = decode_instruction(&data);
+        }
+
+        let data = borsh::to_vec(&Instruction::Claim {
+            indices: vec![1, 2],
+        })
+        .unwrap();
+        assert!(matches!(
+            decode_instruction(&data),
+            Ok(Instruction::Claim { indices }) if indices == [1, 2]
+        ));
+    }
+
+    #[test]
+    fn test_claim_never_sets_padding_bits() {
+        let mut data = card_bytes(9, &[0, 0]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+
+        card.claim(8).unwrap();
+        assert_eq!(card.bits.get(8), Ok(true));
+        for index in 9..16 {
+            assert!(matches!(card.claim(index), Err(ProgramError::Custom(1))));
+        }
+        assert_eq!(card.header.claimed, 1);
+        assert_eq!(data[data.len() - 1], 0b0000_0001);
+    }
+
+    #[test]
+    fn test_set_all_masks_final_byte() {
+        let mut bytes = [0u8; 1];
+        let mut bits = Bits(&mut bytes);
+        bits.set_all(8);
+        assert_eq!(bits.count_ones_in_range(0, 8), 8);
+        bits.clear_all();
+        assert_eq!(bytes, [0]);
+
+        let mut bytes = [0u8; 2];
+        let mut bits = Bits(&mut bytes);
+        bits.set_all(13);
+        assert_eq!(bits.count_ones_in_range(0, 13), 13);
+        // Bits 13..16 are padding
+        assert_eq!(bytes, [0xFF, 0b0001_1111]);
+
+        let mut bits = Bits(&mut bytes);
+        bits.clear_all();
+        assert_eq!(bytes, [0, 0]);
+    }
+
+    #[test]
+    fn test_claim_stops_at_capacity() {
+        let mut data = card_bytes(10, &[0, 0]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+
+        for index in 0..10 {
+            card.claim(index).unwrap();
+        }
+        assert_eq!(card.header.claimed, 10);
+        assert!(card.is_full());
+
+        assert_eq!(card.claim(10), Err(ProgramError::Custom(1)));
+        assert_eq!(card.claim(9), Err(ProgramError::Custom(2)));
+        assert_eq!(card.header.claimed, 10);
+
+        // A counter that has drifted to the limit is caught rather than bumped
+        let mut data = card_bytes(10, &[0, 0]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+        card.header.claimed = 10;
+        assert_eq!(card.claim(0), Err(ProgramError::InvalidAccountData));
+        card.header.claimed = u64::MAX;
+        assert_eq!(card.claim(0), Err(ProgramError::ArithmeticOverflow));
+        assert_eq!(card.bits.get(0), Ok(false));
+    }
+
+    #[test]
+    fn test_claim_all_is_a_no_op_on_full_card() {
+        let mut data = card_bytes(13, &[0b0100_0001, 0]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+
+        assert_eq!(card.claim_all(0), Ok(11));
+        assert_eq!(card.header.claimed, 13);
+        assert_eq!(card.header.total_claims, 11);
+        assert!(card.is_full());
+
+        assert_eq!(card.claim_all(0), Ok(0));
+        assert_eq!(card.header.claimed, 13);
+        assert_eq!(card.header.total_claims, 11);
+        assert_eq!(card.bits.0, [0xFF, 0b0001_1111]);
+    }
+
+    #[test]
+    fn test_capacity_construction() {
+        assert_eq!(Capacity::new(1).unwrap().get(), 1);
+        assert_eq!(Capacity::new(MAX_CAPACITY).unwrap().get(), MAX_CAPACITY);
+        assert!(matches!(Capacity::new(0), Err(Error::InvalidCapacity)));
+        assert!(matches!(
+            Capacity::new(MAX_CAPACITY + 1),
+            Err(Error::InvalidCapacity)
+        ));
+        assert!(matches!(
+            Capacity::new(u64::MAX),
+            Err(Error::InvalidCapacity)
+        ));
+    }
+
+    #[test]
+    fn test_claim_fee_is_summed_and_checked() {
+        assert_eq!(claim_fee(5_000, 100).unwrap(), 500_000);
+        assert_eq!(claim_fee(0, usize::MAX).unwrap(), 0);
+        assert_eq!(claim_fee(u64::MAX, 1).unwrap(), u64::MAX);
+        assert_eq!(
+            claim_fee(u64::MAX / 2 + 1, 2),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+    }
+
+    #[test]
+    fn test_unchecked_counts_loads_over_claimed_card() {
+        let mut data = card_bytes(8, &[0b0000_0011]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+        card.header.claimed = 9;
+        card.header.update_checksum();
+
+        assert!(matches!(
+            Punchcard::from_bytes(&mut data),
+            Err(ProgramError::InvalidAccountData)
+        ));
+        let card = Punchcard::from_bytes_unchecked_counts(&mut data).unwrap();
+        assert_eq!(card.header.claimed, 9);
+        assert_eq!(card.bits.get(1), Ok(true));
+    }
+
+    #[test]
+    fn test_recount_ignores_padding_bits() {
+        let mut data = card_bytes(12, &[0b0000_0110, 0b0100_1000]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+        card.header.claimed = 40;
+        card.header.update_checksum();
+
+        let mut card = Punchcard::from_bytes_unchecked_counts(&mut data).unwrap();
+        card.recount();
+        assert_eq!(card.header.claimed, 3);
+    }
+
+    #[test]
+    fn test_from_bytes_distinguishes_truncated_header() {
+        let mut data = card_bytes(16, &[0, 0]);
+
+        let header_len = size_of::<PunchcardHeader>();
+        assert!(matches!(
+            Punchcard::from_bytes(&mut data[..header_len - 1]),
+            Err(ProgramError::AccountDataTooSmall)
+        ));
+        // Header intact, but one bitset byte short of its capacity
+        assert!(matches!(
+            Punchcard::from_bytes(&mut data[..header_len + 1]),
+            Err(ProgramError::InvalidAccountData)
+        ));
+    }
+
+    #[test]
+    fn test_next_unclaimed_from_wraps() {
+        // Claimed: 0, 1, 5..=9
+        let mut data = card_bytes(10, &[0b1110_0011, 0b0000_0011]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+
+        assert_eq!(card.next_unclaimed_from(3), Some(3));
+        assert_eq!(card.next_unclaimed_from(5), Some(2));
+        assert_eq!(card.next_unclaimed_from(25), Some(2));
+
+        let mut data = card_bytes(10, &[0xFF, 0b0000_0011]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+        assert_eq!(card.next_unclaimed_from(4), None);
+    }
+
+    #[test]
+    fn test_find_next_unclaimed() {
+        // Claimed: 0, 1, 5..=9
+        let mut data = card_bytes(12, &[0b1110_0011, 0b0000_0011]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+
+        assert_eq!(card.find_next_unclaimed(0), Some(2));
+        assert_eq!(card.find_next_unclaimed(3), Some(3));
+        assert_eq!(card.find_next_unclaimed(5), Some(10));
+        assert_eq!(card.find_next_unclaimed(11), Some(11));
+        assert_eq!(card.find_next_unclaimed(12), None);
+        assert_eq!(card.find_next_unclaimed(u64::MAX), None);
+
+        // Padding past `capacity` is never returned
+        let mut data = card_bytes(12, &[0xFF, 0b0000_1111]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+        assert_eq!(card.find_next_unclaimed(3), None);
+    }
+
+    #[test]
+    fn test_find_next_unclaimed_partitions() {
+        let mut data = card_bytes(40, &[0xFF, 0b0101_0101, 0, 0xFF, 0b1000_0000]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+
+        // Four workers each drain their own quarter of the card
+        let mut found = Vec::new();
+        for start in [0, 10, 20, 30] {
+            let mut cursor = start;
+            while let Some(index) = card.find_next_unclaimed(cursor).filter(|&i| i < start + 10) {
+                card.claim(index).unwrap();
+                found.push(index);
+                cursor = index + 1;
+            }
+        }
+
+        assert_eq!(
+            found,
+            [
+                9, 11, 13, 15, 16, 17, 18, 19, 20, 21, 22, 23, 32, 33, 34, 35, 36, 37, 38
+            ]
+        );
+        assert!(card.is_full());
+    }
+
+    #[test]
+    fn test_from_bytes_with_tail() {
+        let mut data = card_bytes(12, &[0b0000_0100, 0]);
+        data.extend_from_slice(&[7, 8, 9]);
+
+        assert!(matches!(
+            Punchcard::from_bytes(&mut data),
+            Err(ProgramError::InvalidAccountData)
+        ));
+
+        let (mut card, tail) = Punchcard::from_bytes_with_tail(&mut data).unwrap();
+        assert_eq!(tail, &[7, 8, 9]);
+        assert_eq!(card.bits.get(2), Ok(true));
+        card.claim(11).unwrap();
+        tail[0] = 42;
+
+        // An exact fit leaves an empty tail
+        let mut data = card_bytes(12, &[0, 0]);
+        let (_, tail) = Punchcard::from_bytes_with_tail(&mut data).unwrap();
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn test_count_ones_matches_naive() {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        for len in [0, 1, 7, 8, 9, 63, 64, 65, 1037, 4096] {
+            let mut bytes: Vec<u8> = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect();
+            let naive: u64 = bytes.iter().map(|b| b.count_ones() as u64).sum();
+
+            // Offset by one byte to exercise an unaligned start as well
+            for start in [0, 1.min(len)] {
+                let skipped: u64 = bytes[..start].iter().map(|b| b.count_ones() as u64).sum();
+                let expected = naive - skipped;
+                let bits = Bits(&mut bytes[start..]);
+                assert_eq!(bits.count_ones(), expected, "len {len} start {start}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_remaining_and_is_full() {
+        for (capacity, bits, claimed, remaining) in [
+            (16, &[0b0000_0101, 0][..], 2, 14),
+            (8, &[0xFF][..], 8, 0),
+            (0, &[][..], 0, 0),
+            // Over-counted, as `from_bytes_unchecked_counts` allows
+            (8, &[0b0000_0001][..], 9, 0),
+        ] {
+            let mut data = card_bytes(capacity, bits);
+            let card = Punchcard::from_bytes_unchecked_counts(&mut data).unwrap();
+            card.header.claimed = claimed;
+            assert_eq!(card.remaining(), remaining, "{capacity} {claimed}");
+            assert_eq!(card.is_full(), remaining == 0, "{capacity} {claimed}");
+        }
+    }
+
+    #[test]
+    fn test_is_claimed() {
+        let mut data = card_bytes(12, &[0, 0]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+        card.claim(3).unwrap();
+        card.claim(9).unwrap();
+
+        for index in 0..12 {
+            assert_eq!(card.is_claimed(index), Ok(index == 3 || index == 9));
+        }
+        // Padding bits in the final byte are out of bounds, not unclaimed
+        assert_eq!(card.is_claimed(12), Err(ProgramError::Custom(1)));
+    }
+
+    #[test]
+    fn test_bits_out_of_range_is_an_error() {
+        let mut bytes = [0u8; 2];
+        let mut bits = Bits(&mut bytes);
+
+        bits.set(15).unwrap();
+        assert_eq!(bits.get(15), Ok(true));
+        for index in [16, 100, u64::MAX] {
+            assert_eq!(bits.get(index), Err(ProgramError::Custom(1)));
+            assert_eq!(bits.set(index), Err(ProgramError::Custom(1)));
+            assert_eq!(bits.clear(index), Err(ProgramError::Custom(1)));
+            assert_eq!(bits.toggle(index), Err(ProgramError::Custom(1)));
+        }
+        assert_eq!(bytes, [0, 0b1000_0000]);
+    }
+
+    #[test]
+    fn test_toggle_tracks_claimed() {
+        let mut data = card_bytes(16, &[0, 0]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+
+        assert_eq!(card.toggle(5), Ok(true));
+        assert_eq!(card.bits.get(5), Ok(true));
+        assert_eq!(card.header.claimed, 1);
+
+        assert_eq!(card.toggle(5), Ok(false));
+        assert_eq!(card.bits.get(5), Ok(false));
+        assert_eq!(card.header.claimed, 0);
+        assert_eq!(card.header.total_claims, 1);
+
+        assert_eq!(card.toggle(16), Err(ProgramError::Custom(1)));
+    }
+
+    #[test]
+    fn test_iter_claimed_and_unclaimed_split_the_card() {
+        // Claimed: 0, 3, 9, 16..24 and 29; padding bits 30 and 31 are set too
+        let mut bytes = [0b0000_1001, 0b0000_0010, 0xFF, 0b1110_0000];
+        let bits = Bits(&mut bytes);
+
+        let claimed: Vec<u64> = bits.iter_claimed(30).collect();
+        let mut expected = vec![0, 3, 9];
+        expected.extend(16..24);
+        expected.push(29);
+        assert_eq!(claimed, expected);
+
+        let unclaimed: Vec<u64> = bits.iter_unclaimed(30).collect();
+        let naive: Vec<u64> = (0..30).filter(|&i| bits.get(i) == Ok(false)).collect();
+        assert_eq!(unclaimed, naive);
+        assert_eq!(unclaimed.len() + claimed.len(), 30);
+
+        assert_eq!(bits.iter_claimed(0).count(), 0);
+        assert_eq!(bits.iter_unclaimed(4).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_set_range_matches_naive() {
+        for (start, end) in [(0, 0), (3, 5), (0, 8), (6, 10), (5, 27), (8, 24), (1, 32)] {
+            let mut bytes = [0u8; 4];
+            Bits(&mut bytes).set_range(start, end);
+
+            let mut expected = [0u8; 4];
+            let mut bits = Bits(&mut expected);
+            for i in start..end {
+                bits.set(i).unwrap();
+            }
+            assert_eq!(bytes, expected, "range {start}..{end}");
+        }
+    }
+
+    #[test]
+    fn test_claim_range_rejects_overlap() {
+        let mut data = card_bytes(20, &[0b0100_0000, 0, 0]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+
+        assert!(matches!(
+            card.claim_range(2, 10),
+            Err(ProgramError::Custom(2))
+        ));
+        assert!(matches!(
+            card.claim_range(10, 21),
+            Err(ProgramError::Custom(1))
+        ));
+        assert!(matches!(
+            card.claim_range(10, 2),
+            Err(ProgramError::InvalidInstructionData)
+        ));
+        assert_eq!(card.header.claimed, 1);
+
+        card.claim_range(7, 20).unwrap();
+        assert_eq!(card.header.claimed, 14);
+        assert_eq!(card.bits.count_ones_in_range(7, 20), 13);
+        assert_eq!(card.bits.get(5), Ok(false));
+    }
+
+    #[test]
+    fn test_find_first_unclaimed() {
+        for (capacity, bits, expected) in [
+            (16, &[0, 0][..], Some(0)),
+            (16, &[0xFF, 0b0000_0111][..], Some(11)),
+            (12, &[0xFF, 0b0000_0111][..], Some(11)),
+            (12, &[0xFF, 0b0000_1111][..], None),
+            (8, &[0xFF][..], None),
+            (0, &[][..], None),
+        ] {
+            let mut data = card_bytes(capacity, bits);
+            let card = Punchcard::from_bytes(&mut data).unwrap();
+            assert_eq!(card.find_first_unclaimed(), expected, "{bits:?}");
+        }
+    }
+
+    #[test]
+    fn test_next_unclaimed_skips_claimed() {
+        // Claimed: 0..=7, 9, 11
+        let mut data = card_bytes(20, &[0xFF, 0b0000_1010, 0]);
+        let card = Punchcard::from_bytes(&mut data).unwrap();
+
+        assert_eq!(card.next_unclaimed(3), vec![8, 10, 12]);
+        assert_eq!(card.next_unclaimed(0), Vec::<u64>::new());
+        assert_eq!(card.next_unclaimed(100).len(), 10);
+        assert_eq!(card.next_unclaimed(100).last(), Some(&19));
+    }
+
+    #[test]
+    fn test_refund_rejects_overflow() {
+        assert_eq!(refund(1_000, 2_000), Ok(3_000));
+        assert_eq!(refund(u64::MAX - 5, 5), Ok(u64::MAX));
+        assert_eq!(
+            refund(u64::MAX - 5, 6),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+    }
+
+    #[test]
+    fn test_unclaim_reverses_claim() {
+        let mut data = card_bytes(16, &[0, 0]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+
+        card.claim(5).unwrap();
+        card.unclaim(5).unwrap();
+        assert_eq!(card.bits.get(5), Ok(false));
+        assert_eq!(card.header.claimed, 0);
+        assert_eq!(card.header.total_claims, 1);
+
+        assert!(matches!(card.unclaim(5), Err(ProgramError::Custom(3))));
+        assert!(matches!(card.unclaim(16), Err(ProgramError::Custom(1))));
+    }
+
+    #[test]
+    fn test_claim_many_rejects_duplicates() {
+        let mut data = card_bytes(16, &[0b0000_1000, 0]);
+        for (indices, code) in [
+            (&[5, 5][..], 15),
+            (&[1, 2, 1][..], 15),
+            // Claimed before the instruction, so the first occurrence fails
+            (&[3, 3][..], 2),
+        ] {
+            let mut card = Punchcard::from_bytes(&mut data).unwrap();
+            assert!(
+                matches!(card.claim_many(indices), Err(ProgramError::Custom(c)) if c == code),
+                "{indices:?}"
+            );
+            assert_eq!(card.bits.0, [0b0000_1000, 0], "{indices:?}");
+            assert_eq!(card.header.claimed, 1);
+        }
+
+        // One slot left: a repeat of it is a duplicate, not an overfull card
+        let mut data = card_bytes(8, &[0b0111_1111]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+        assert_eq!(card.claim_many(&[7, 7]), Err(ProgramError::Custom(15)));
+        assert_eq!(card.bits.0, [0b0111_1111]);
+        assert_eq!(card.header.claimed, 7);
+    }
+
+    #[test]
+    fn test_check_claimable_leaves_card_unchanged() {
+        // Claimed: 3, 9
+        let mut data = card_bytes(12, &[0b0000_1000, 0b0000_0010]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+
+        assert_eq!(card.check_claimable(&[0, 1, 2]), Ok(()));
+        // The claimed index comes last, after indices that would have been set
+        assert_eq!(
+            card.check_claimable(&[0, 1, 9]),
+            Err(ProgramError::Custom(2))
+        );
+        // Each index is checked in turn, so the first failure decides the error
+        assert_eq!(
+            card.check_claimable(&[0, 3, 12]),
+            Err(ProgramError::Custom(2))
+        );
+        assert_eq!(
+            card.check_claimable(&[0, 12, 3]),
+            Err(ProgramError::Custom(1))
+        );
+        assert_eq!(card.claim_many(&[0, 3, 12]), Err(ProgramError::Custom(2)));
+        assert_eq!(card.bits.0, [0b0000_1000, 0b0000_0010]);
+
+        assert_eq!(card.claim_many(&[0, 1, 9]), Err(ProgramError::Custom(2)));
+        assert_eq!(card.bits.0, [0b0000_1000, 0b0000_0010]);
+        assert_eq!(card.header.claimed, 2);
+        assert_eq!(card.header.total_claims, 0);
+
+        card.claim_many(&[0, 1]).unwrap();
+        assert_eq!(card.check_claimable(&[0]), Err(ProgramError::Custom(2)));
+    }
+
+    #[test]
+    fn test_claim_many_on_full_card_fails_fast() {
+        let mut data = card_bytes(8, &[0xFF]);
+        let mut card = Punchcard::from_bytes(&mut data).unwrap();
+        assert!(matches!(
+            card.claim_many(&[0, 1, 2]),
+            Err(ProgramError::Custom(7))
+        ));
+    }
 }
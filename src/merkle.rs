@@ -0,0 +1,24 @@
+//! Merkle trees of indices, for `ClaimWithProof` and
+//! `Punchcard::claimed_merkle_root`. Pairs are hashed in sorted order, so a
+//! proof is just the sibling hashes from leaf to root.
+
+use crate::keccak;
+
+/// Hash of the leaf for `index`.
+pub fn leaf(index: u64) -> [u8; 32] {
+    keccak(&[&index.to_le_bytes()[..]])
+}
+
+/// Hash of the node above `a` and `b`, in either order.
+pub fn parent(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+    keccak(&[&low[..], &high[..]])
+}
+
+/// Whether `proof` leads from the leaf for `index` to `root`.
+pub fn verify(root: &[u8; 32], index: u64, proof: &[[u8; 32]]) -> bool {
+    proof
+        .iter()
+        .fold(leaf(index), |node, sibling| parent(&node, sibling))
+        == *root
+}
@@ -0,0 +1,144 @@
+//! Return data written by the read-only instructions. Each encoder the program
+//! uses sits next to the decoder clients use, so the two layouts cannot drift.
+
+use pinocchio::pubkey::Pubkey;
+
+use crate::{ID, PunchcardHeader};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Length of `Ping` return data: the program ID followed by the version string.
+pub const PING_LEN: usize = 32 + VERSION.len();
+
+pub fn encode_ping() -> [u8; PING_LEN] {
+    let mut data = [0u8; PING_LEN];
+    data[..32].copy_from_slice(&ID);
+    data[32..].copy_from_slice(VERSION.as_bytes());
+    data
+}
+
+/// The program ID and crate version reported by `Ping`.
+pub fn decode_ping(data: &[u8]) -> Option<(Pubkey, &str)> {
+    let (id, version) = data.split_first_chunk::<32>()?;
+    Some((*id, core::str::from_utf8(version).ok()?))
+}
+
+/// `claimed` then `capacity`, both little-endian `u64`s.
+pub fn encode_claim(claimed: u64, capacity: u64) -> [u8; 16] {
+    let mut data = [0u8; 16];
+    data[..8].copy_from_slice(&claimed.to_le_bytes());
+    data[8..].copy_from_slice(&capacity.to_le_bytes());
+    data
+}
+
+/// The card's `claimed` count and capacity after a `Claim`.
+pub fn decode_claim(data: &[u8]) -> Option<(u64, u64)> {
+    let data: &[u8; 16] = data.try_into().ok()?;
+    let (claimed, capacity) = data.split_at(8);
+    Some((
+        u64::from_le_bytes(claimed.try_into().unwrap()),
+        u64::from_le_bytes(capacity.try_into().unwrap()),
+    ))
+}
+
+/// Length of `Query` return data: the authority, then `capacity` and `claimed`
+/// as little-endian `u64`s.
+pub const QUERY_LEN: usize = 32 + 8 + 8;
+
+pub fn encode_query(header: &PunchcardHeader) -> [u8; QUERY_LEN] {
+    let mut data = [0u8; QUERY_LEN];
+    data[..32].copy_from_slice(&header.authority);
+    data[32..40].copy_from_slice(&header.capacity.to_le_bytes());
+    data[40..].copy_from_slice(&header.claimed.to_le_bytes());
+    data
+}
+
+/// The authority, capacity and claimed count reported by `Query`.
+pub fn decode_query(data: &[u8]) -> Option<(Pubkey, u64, u64)> {
+    let data: &[u8; QUERY_LEN] = data.try_into().ok()?;
+    let (authority, counts) = data.split_at(32);
+    let (capacity, claimed) = counts.split_at(8);
+    Some((
+        authority.try_into().unwrap(),
+        u64::from_le_bytes(capacity.try_into().unwrap()),
+        u64::from_le_bytes(claimed.try_into().unwrap()),
+    ))
+}
+
+pub fn encode_claim_expired(expired: bool) -> [u8; 1] {
+    [expired as u8]
+}
+
+/// Whether `ClaimExpired` found the claim past its redemption window.
+pub fn decode_claim_expired(data: &[u8]) -> Option<bool> {
+    match data {
+        [0] => Some(false),
+        [1] => Some(true),
+        _ => None,
+    }
+}
+
+pub fn encode_diagnose(failures: u8) -> [u8; 1] {
+    [failures]
+}
+
+/// The `DIAGNOSE_*` failure bits reported by `Diagnose`.
+pub fn decode_diagnose(data: &[u8]) -> Option<u8> {
+    match data {
+        [failures] => Some(*failures),
+        _ => None,
+    }
+}
+
+pub fn encode_touch_claim(newly_claimed: bool) -> [u8; 1] {
+    [newly_claimed as u8]
+}
+
+/// Whether `TouchClaim` made a new claim (`true`) or found the index already
+/// claimed (`false`).
+pub fn decode_touch_claim(data: &[u8]) -> Option<bool> {
+    match data {
+        [0] => Some(false),
+        [1] => Some(true),
+        _ => None,
+    }
+}
+
+pub fn encode_claim_random(index: u64) -> [u8; 8] {
+    index.to_le_bytes()
+}
+
+/// The index `ClaimRandom` picked and claimed.
+pub fn decode_claim_random(data: &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(data.try_into().ok()?))
+}
+
+/// Most indices `ClaimPercent` reports after its `u64` count, keeping the return
+/// data within the runtime's 1024-byte limit.
+pub const CLAIM_PERCENT_MAX_INDICES: usize = 127;
+
+/// The number of indices claimed, followed by the first
+/// `CLAIM_PERCENT_MAX_INDICES` of them, all little-endian `u64`s.
+pub fn encode_claim_percent(indices: &[u64]) -> Vec<u8> {
+    let reported = &indices[..indices.len().min(CLAIM_PERCENT_MAX_INDICES)];
+    let mut data = Vec::with_capacity(8 * (1 + reported.len()));
+    data.extend_from_slice(&(indices.len() as u64).to_le_bytes());
+    for index in reported {
+        data.extend_from_slice(&index.to_le_bytes());
+    }
+    data
+}
+
+/// How many indices `ClaimPercent` claimed, and the lowest of them (all of
+/// them, unless there were more than `CLAIM_PERCENT_MAX_INDICES`).
+pub fn decode_claim_percent(data: &[u8]) -> Option<(u64, Vec<u64>)> {
+    let (count, indices) = data.split_first_chunk::<8>()?;
+    if indices.len() % 8 != 0 {
+        return None;
+    }
+    let indices = indices
+        .chunks_exact(8)
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    Some((u64::from_le_bytes(*count), indices))
+}
@@ -1,22 +1,64 @@
-use borsh::BorshSerialize;
-use litesvm::LiteSVM;
+use litesvm::{LiteSVM, types::TransactionResult};
+// The program's own enum, so the data built here can never drift from it
+use punchcard::Instruction as PunchcardInstruction;
+use punchcard::{AUTHORITY_OFFSET, BITS_OFFSET, CAPACITY_OFFSET, CLAIMED_OFFSET};
 use solana_sdk::{
-    instruction::{AccountMeta, Instruction},
+    instruction::{AccountMeta, Instruction, InstructionError},
     pubkey::Pubkey,
     signature::Keypair,
     signer::Signer,
-    transaction::Transaction,
+    transaction::{Transaction, TransactionError},
 };
 
 const PROGRAM_ID: Pubkey = solana_sdk::pubkey!("pcWKVSdcdDUKabPz4pVfaQ2jMod1kWv3LqeQivjKXiF");
+const HEADER_LEN: usize = punchcard::PUNCHCARD_HEADER_LEN;
 
-#[derive(BorshSerialize)]
-enum PunchcardInstruction {
-    Create { capacity: u64 },
-    Claim { indices: Vec<u64> },
+fn create_ix(payer: &Pubkey, punchcard: &Pubkey, capacity: u64) -> Instruction {
+    create_redeemable_ix(payer, punchcard, capacity, 0)
 }
 
-fn create_ix(payer: &Pubkey, punchcard: &Pubkey, capacity: u64) -> Instruction {
+fn create_redeemable_ix(
+    payer: &Pubkey,
+    punchcard: &Pubkey,
+    capacity: u64,
+    redemption_window: u64,
+) -> Instruction {
+    create_accounts_ix(
+        payer,
+        punchcard,
+        PunchcardInstruction::Create {
+            capacity,
+            redemption_window,
+            close_recipient: [0; 32],
+            extra_lamports: 0,
+        },
+    )
+}
+
+fn create_with_recipient_ix(
+    payer: &Pubkey,
+    punchcard: &Pubkey,
+    capacity: u64,
+    close_recipient: &Pubkey,
+) -> Instruction {
+    create_accounts_ix(
+        payer,
+        punchcard,
+        PunchcardInstruction::Create {
+            capacity,
+            redemption_window: 0,
+            close_recipient: close_recipient.to_bytes(),
+            extra_lamports: 0,
+        },
+    )
+}
+
+/// Any of the create instructions, which all share the same accounts.
+fn create_accounts_ix(
+    payer: &Pubkey,
+    punchcard: &Pubkey,
+    instruction: PunchcardInstruction,
+) -> Instruction {
     Instruction {
         program_id: PROGRAM_ID,
         accounts: vec![
@@ -24,7 +66,19 @@ fn create_ix(payer: &Pubkey, punchcard: &Pubkey, capacity: u64) -> Instruction {
             AccountMeta::new(*punchcard, true),
             AccountMeta::new_readonly(Pubkey::new_from_array(pinocchio_system::ID), false),
         ],
-        data: borsh::to_vec(&PunchcardInstruction::Create { capacity }).unwrap(),
+        data: borsh::to_vec(&instruction).unwrap(),
+    }
+}
+
+fn create_pda_ix(payer: &Pubkey, punchcard: &Pubkey, capacity: u64, seed: [u8; 32]) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(*punchcard, false),
+            AccountMeta::new_readonly(Pubkey::new_from_array(pinocchio_system::ID), false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::CreatePda { capacity, seed }).unwrap(),
     }
 }
 
@@ -39,114 +93,3734 @@ fn claim_ix(authority: &Pubkey, punchcard: &Pubkey, indices: Vec<u64>) -> Instru
     }
 }
 
-fn read_punchcard(svm: &LiteSVM, punchcard: &Pubkey) -> Option<(Pubkey, u64, u64, Vec<u8>)> {
-    let account = svm.get_account(punchcard)?;
-    let data = &account.data;
-    if data.len() < 48 {
-        return None;
+fn claim_expired_ix(punchcard: &Pubkey, index: u64) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![AccountMeta::new_readonly(*punchcard, false)],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimExpired { index }).unwrap(),
+    }
+}
+
+fn diagnose_ix(punchcard: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![AccountMeta::new_readonly(*punchcard, false)],
+        data: borsh::to_vec(&PunchcardInstruction::Diagnose).unwrap(),
+    }
+}
+
+fn query_ix(punchcard: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![AccountMeta::new_readonly(*punchcard, false)],
+        data: borsh::to_vec(&PunchcardInstruction::Query).unwrap(),
+    }
+}
+
+fn claim_all_ix(authority: &Pubkey, punchcard: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimAll).unwrap(),
+    }
+}
+
+fn set_note_ix(authority: &Pubkey, punchcard: &Pubkey, note: &str) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::SetNote {
+            note: note.to_string(),
+        })
+        .unwrap(),
+    }
+}
+
+fn read_note(svm: &LiteSVM, punchcard: &Pubkey) -> String {
+    let account = svm.get_account(punchcard).unwrap();
+    let note = &account.data[80..144];
+    let len = note.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    String::from_utf8(note[..len].to_vec()).unwrap()
+}
+
+fn create_idempotent_ix(payer: &Pubkey, punchcard: &Pubkey, capacity: u64) -> Instruction {
+    create_accounts_ix(
+        payer,
+        punchcard,
+        PunchcardInstruction::CreateIdempotent {
+            capacity,
+            redemption_window: 0,
+            close_recipient: [0; 32],
+        },
+    )
+}
+
+fn claim_and_transfer_ix(
+    authority: &Pubkey,
+    punchcard: &Pubkey,
+    indices: Vec<u64>,
+    new_authority: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimAndTransfer {
+            indices,
+            new_authority: new_authority.to_bytes(),
+        })
+        .unwrap(),
+    }
+}
+
+fn claim_next_ix(authority: &Pubkey, punchcard: &Pubkey, count: u64) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimNext { count }).unwrap(),
+    }
+}
+
+fn reset_and_resize_ix(authority: &Pubkey, punchcard: &Pubkey, new_capacity: u64) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+            AccountMeta::new_readonly(Pubkey::new_from_array(pinocchio_system::ID), false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ResetAndResize { new_capacity }).unwrap(),
+    }
+}
+
+fn migrate_ix(authority: &Pubkey, punchcard: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+            AccountMeta::new_readonly(Pubkey::new_from_array(pinocchio_system::ID), false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::Migrate).unwrap(),
+    }
+}
+
+fn touch_claim_ix(authority: &Pubkey, punchcard: &Pubkey, index: u64) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::TouchClaim { index }).unwrap(),
+    }
+}
+
+fn claim_random_ix(authority: &Pubkey, punchcard: &Pubkey, seed: u64) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimRandom { seed }).unwrap(),
+    }
+}
+
+fn claim_percent_ix(authority: &Pubkey, punchcard: &Pubkey, bps: u16) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimPercent { bps }).unwrap(),
+    }
+}
+
+fn contribute_ix(contributor: &Pubkey, punchcard: &Pubkey, lamports: u64) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*contributor, true),
+            AccountMeta::new(*punchcard, false),
+            AccountMeta::new_readonly(Pubkey::new_from_array(pinocchio_system::ID), false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::Contribute { lamports }).unwrap(),
+    }
+}
+
+fn claim_until_ix(
+    authority: &Pubkey,
+    punchcard: &Pubkey,
+    indices: Vec<u64>,
+    valid_until_slot: u64,
+) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimUntil {
+            indices,
+            valid_until_slot,
+        })
+        .unwrap(),
+    }
+}
+
+fn transfer_authority_ix(
+    authority: &Pubkey,
+    punchcard: &Pubkey,
+    new_authority: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::TransferAuthority {
+            new_authority: new_authority.to_bytes(),
+        })
+        .unwrap(),
+    }
+}
+
+fn close_ix(authority: &Pubkey, punchcard: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::Close).unwrap(),
+    }
+}
+
+fn unclaim_ix(authority: &Pubkey, punchcard: &Pubkey, indices: Vec<u64>) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::Unclaim { indices }).unwrap(),
+    }
+}
+
+fn claim_range_ix(authority: &Pubkey, punchcard: &Pubkey, start: u64, end: u64) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimRange { start, end }).unwrap(),
+    }
+}
+
+fn recount_ix(authority: &Pubkey, punchcard: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::Recount).unwrap(),
+    }
+}
+
+fn resize_ix(authority: &Pubkey, punchcard: &Pubkey, new_capacity: u64) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+            AccountMeta::new_readonly(Pubkey::new_from_array(pinocchio_system::ID), false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::Resize { new_capacity }).unwrap(),
+    }
+}
+
+fn create_with_close_authority_ix(
+    payer: &Pubkey,
+    punchcard: &Pubkey,
+    capacity: u64,
+    close_authority: &Pubkey,
+) -> Instruction {
+    create_accounts_ix(
+        payer,
+        punchcard,
+        PunchcardInstruction::CreateWithCloseAuthority {
+            capacity,
+            redemption_window: 0,
+            close_recipient: [0; 32],
+            close_authority: close_authority.to_bytes(),
+        },
+    )
+}
+
+fn set_paused_ix(authority: &Pubkey, punchcard: &Pubkey, paused: bool) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::SetPaused { paused }).unwrap(),
+    }
+}
+
+fn multi_claim_ix(
+    authority: &Pubkey,
+    punchcards: &[Pubkey],
+    claims: Vec<(u8, Vec<u64>)>,
+) -> Instruction {
+    let mut accounts = vec![AccountMeta::new(*authority, true)];
+    accounts.extend(punchcards.iter().map(|key| AccountMeta::new(*key, false)));
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts,
+        data: borsh::to_vec(&PunchcardInstruction::MultiClaim { claims }).unwrap(),
+    }
+}
+
+fn add_delegate_ix(authority: &Pubkey, punchcard: &Pubkey, delegate: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::AddDelegate {
+            delegate: delegate.to_bytes(),
+        })
+        .unwrap(),
+    }
+}
+
+fn remove_delegate_ix(authority: &Pubkey, punchcard: &Pubkey, delegate: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::RemoveDelegate {
+            delegate: delegate.to_bytes(),
+        })
+        .unwrap(),
+    }
+}
+
+fn set_merkle_root_ix(
+    authority: &Pubkey,
+    punchcard: &Pubkey,
+    merkle_root: [u8; 32],
+) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::SetMerkleRoot { merkle_root }).unwrap(),
+    }
+}
+
+fn claim_with_proof_ix(
+    claimer: &Pubkey,
+    punchcard: &Pubkey,
+    index: u64,
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*claimer, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimWithProof { index, proof }).unwrap(),
+    }
+}
+
+fn set_claim_signer_ix(authority: &Pubkey, punchcard: &Pubkey, signer: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::SetClaimSigner {
+            signer_pubkey: signer.to_bytes(),
+        })
+        .unwrap(),
+    }
+}
+
+fn claim_signed_ix(claimer: &Pubkey, punchcard: &Pubkey, index: u64) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*claimer, true),
+            AccountMeta::new(*punchcard, false),
+            AccountMeta::new_readonly(
+                Pubkey::from_str_const("Sysvar1nstructions1111111111111111111111111"),
+                false,
+            ),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimSigned { index }).unwrap(),
     }
+}
 
-    let authority = Pubkey::try_from(&data[0..32]).unwrap();
-    let capacity = u64::from_le_bytes(data[32..40].try_into().unwrap());
-    let claimed = u64::from_le_bytes(data[40..48].try_into().unwrap());
-    let bits = data[48..].to_vec();
+/// An Ed25519 program instruction verifying `signer`'s signature over `message`,
+/// with the key, signature and message all in its own data.
+fn ed25519_ix(signer: &Keypair, message: &[u8]) -> Instruction {
+    let here = u16::MAX;
+    let offsets = [48, here, 16, here, 112, message.len() as u16, here];
+    let mut data = vec![1, 0];
+    for offset in offsets {
+        data.extend_from_slice(&offset.to_le_bytes());
+    }
+    data.extend_from_slice(signer.pubkey().as_ref());
+    data.extend_from_slice(signer.sign_message(message).as_ref());
+    data.extend_from_slice(message);
+    Instruction {
+        program_id: Pubkey::new_from_array(punchcard::ED25519_PROGRAM_ID),
+        accounts: vec![],
+        data,
+    }
+}
+
+fn claim_for_fee_ix(
+    authority: &Pubkey,
+    punchcard: &Pubkey,
+    payer: &Pubkey,
+    indices: Vec<u64>,
+    fee: u64,
+) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(Pubkey::new_from_array(pinocchio_system::ID), false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::ClaimForFee { indices, fee }).unwrap(),
+    }
+}
+
+fn toggle_ix(authority: &Pubkey, punchcard: &Pubkey, indices: Vec<u64>) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::Toggle { indices }).unwrap(),
+    }
+}
+
+fn create_with_recipients_ix(payer: &Pubkey, punchcard: &Pubkey, capacity: u64) -> Instruction {
+    create_accounts_ix(
+        payer,
+        punchcard,
+        PunchcardInstruction::CreateWithRecipients {
+            capacity,
+            redemption_window: 0,
+            close_recipient: [0; 32],
+        },
+    )
+}
+
+fn set_recipients_ix(
+    authority: &Pubkey,
+    punchcard: &Pubkey,
+    start: u64,
+    recipients: &[Pubkey],
+) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*punchcard, false),
+        ],
+        data: borsh::to_vec(&PunchcardInstruction::SetRecipients {
+            start,
+            recipients: recipients.iter().map(Pubkey::to_bytes).collect(),
+        })
+        .unwrap(),
+    }
+}
+
+fn instruction_error(result: TransactionResult) -> Option<InstructionError> {
+    match result.err()?.err {
+        TransactionError::InstructionError(_, err) => Some(err),
+        _ => None,
+    }
+}
+
+fn read_punchcard(svm: &LiteSVM, punchcard: &Pubkey) -> Option<(Pubkey, u64, u64, Vec<u8>)> {
+    let account = svm.get_account(punchcard)?;
+    let data = &account.data;
+    if data.len() < HEADER_LEN {
+        return None;
+    }
+
+    let authority = Pubkey::try_from(&data[AUTHORITY_OFFSET..AUTHORITY_OFFSET + 32]).unwrap();
+    let capacity = u64::from_le_bytes(
+        data[CAPACITY_OFFSET..CAPACITY_OFFSET + 8]
+            .try_into()
+            .unwrap(),
+    );
+    let claimed = u64::from_le_bytes(data[CLAIMED_OFFSET..CLAIMED_OFFSET + 8].try_into().unwrap());
+    let bits = data[BITS_OFFSET..BITS_OFFSET + capacity.div_ceil(8) as usize].to_vec();
+
+    Some((authority, capacity, claimed, bits))
+}
+
+/// Checks the accounting invariants every instruction must preserve: `claimed`
+/// matches the set bits below `capacity` and never exceeds it.
+fn assert_card_consistent(svm: &LiteSVM, punchcard: &Pubkey) {
+    let (_, capacity, claimed, bits) = read_punchcard(svm, punchcard).unwrap();
+    let popcount = (0..capacity)
+        .filter(|&i| bits[(i / 8) as usize] & (1 << (i % 8)) != 0)
+        .count() as u64;
+    assert_eq!(claimed, popcount, "claimed does not match the bitset");
+    assert!(claimed <= capacity, "claimed exceeds capacity");
+}
+
+fn setup() -> (LiteSVM, Keypair) {
+    let mut svm = LiteSVM::new();
+    svm.add_program_from_file(PROGRAM_ID, "target/deploy/punchcard.so")
+        .expect("Run `cargo build-sbf` first");
+    let payer = Keypair::new();
+    svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
+    (svm, payer)
+}
+
+#[test]
+fn test_create_punchcard() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (authority, capacity, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(authority, payer.pubkey());
+    assert_eq!(capacity, 16);
+    assert_eq!(claimed, 0);
+    assert_eq!(bits.len(), 2);
+    assert!(bits.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_claim_single_index() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+    assert_eq!(bits[0] & (1 << 5), 1 << 5);
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_claim_multiple_indices() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            vec![0, 3, 7, 12],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 4);
+    assert_eq!(bits[0] & (1 << 0), 1 << 0);
+    assert_eq!(bits[0] & (1 << 3), 1 << 3);
+    assert_eq!(bits[0] & (1 << 7), 1 << 7);
+    assert_eq!(bits[1] & (1 << 4), 1 << 4); // index 12 = byte 1, bit 4
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_claim_already_claimed_fails() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert!(svm.send_transaction(tx).is_err());
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_claim_out_of_bounds_fails() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![16])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert!(svm.send_transaction(tx).is_err());
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_claim_wrong_authority_fails() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let wrong = Keypair::new();
+    svm.airdrop(&wrong.pubkey(), 1_000_000_000).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&wrong.pubkey(), &punchcard.pubkey(), vec![5])],
+        Some(&wrong.pubkey()),
+        &[&wrong],
+        svm.latest_blockhash(),
+    );
+    assert!(svm.send_transaction(tx).is_err());
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_claim_all_closes_account() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 4)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let balance_before = svm.get_account(&payer.pubkey()).unwrap().lamports;
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            vec![0, 1, 2, 3],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // Account should be closed
+    match svm.get_account(&punchcard.pubkey()) {
+        None => {}
+        Some(acc) => {
+            assert!(acc.lamports == 0 || acc.owner == Pubkey::new_from_array(pinocchio_system::ID))
+        }
+    }
+
+    // Rent returned
+    let balance_after = svm.get_account(&payer.pubkey()).unwrap().lamports;
+    assert!(balance_after > balance_before);
+}
+
+#[test]
+fn test_various_capacities() {
+    let (mut svm, payer) = setup();
+
+    for capacity in [1, 7, 8, 9, 15, 16, 17, 64, 100] {
+        let punchcard = Keypair::new();
+        let tx = Transaction::new_signed_with_payer(
+            &[create_ix(&payer.pubkey(), &punchcard.pubkey(), capacity)],
+            Some(&payer.pubkey()),
+            &[&payer, &punchcard],
+            svm.latest_blockhash(),
+        );
+        svm.send_transaction(tx).unwrap();
+
+        let (_, cap, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+        assert_eq!(cap, capacity);
+        assert_eq!(claimed, 0);
+        assert_eq!(bits.len(), ((capacity + 7) / 8) as usize);
+    }
+}
+
+#[test]
+fn test_claim_redemption_expiry() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_redeemable_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            16,
+            10_000,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let account = svm.get_account(&punchcard.pubkey()).unwrap();
+    assert_eq!(account.data.len(), HEADER_LEN + 2 + 16 * 2);
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![3])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_expired_ix(&punchcard.pubkey(), 3)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert_eq!(meta.return_data.data, vec![0]);
+
+    // Unclaimed indices have no redemption window to report on
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_expired_ix(&punchcard.pubkey(), 4)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert!(svm.send_transaction(tx).is_err());
+
+    svm.warp_to_slot(20_000);
+    svm.expire_blockhash();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_expired_ix(&punchcard.pubkey(), 3)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert_eq!(meta.return_data.data, vec![1]);
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_diagnose_reports_failures() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1, 9]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[diagnose_ix(&punchcard.pubkey())],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert_eq!(meta.return_data.data, vec![0]);
+
+    // Bump the claimed counter without touching the bits or the checksum
+    let mut account = svm.get_account(&punchcard.pubkey()).unwrap();
+    account.data[48..56].copy_from_slice(&3u64.to_le_bytes());
+    svm.set_account(punchcard.pubkey(), account).unwrap();
+    svm.expire_blockhash();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[diagnose_ix(&punchcard.pubkey())],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert_eq!(
+        meta.return_data.data,
+        vec![punchcard::DIAGNOSE_POPCOUNT_MISMATCH | punchcard::DIAGNOSE_CHECKSUM_MISMATCH]
+    );
+
+    // A layout the program does not know hides every check that reads past it
+    let version_at = core::mem::offset_of!(punchcard::PunchcardHeader, version);
+    for (at, byte, failure) in [
+        (
+            version_at,
+            punchcard::CURRENT_VERSION + 1,
+            punchcard::DIAGNOSE_BAD_VERSION,
+        ),
+        (0, b'X', punchcard::DIAGNOSE_BAD_MAGIC),
+    ] {
+        let mut account = svm.get_account(&punchcard.pubkey()).unwrap();
+        account.data[at] = byte;
+        svm.set_account(punchcard.pubkey(), account).unwrap();
+        svm.expire_blockhash();
+
+        let tx = Transaction::new_signed_with_payer(
+            &[diagnose_ix(&punchcard.pubkey())],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        let meta = svm.send_transaction(tx).unwrap();
+        assert_eq!(meta.return_data.data, vec![failure]);
+    }
+}
+
+#[test]
+fn test_claim_all_remaining_closes_account() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 13),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 5, 9]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let balance_before = svm.get_account(&payer.pubkey()).unwrap().lamports;
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_all_ix(&payer.pubkey(), &punchcard.pubkey())],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    match svm.get_account(&punchcard.pubkey()) {
+        None => {}
+        Some(acc) => {
+            assert!(acc.lamports == 0 || acc.owner == Pubkey::new_from_array(pinocchio_system::ID))
+        }
+    }
+
+    let balance_after = svm.get_account(&payer.pubkey()).unwrap().lamports;
+    assert!(balance_after > balance_before);
+}
+
+#[cfg(feature = "debug-ops")]
+#[test]
+fn test_failed_claim_does_not_persist_bits() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[Instruction {
+            program_id: PROGRAM_ID,
+            accounts: vec![
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new(punchcard.pubkey(), false),
+            ],
+            data: borsh::to_vec(&PunchcardInstruction::ClaimThenFail {
+                indices: vec![2, 3, 11],
+            })
+            .unwrap(),
+        }],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert!(svm.send_transaction(tx).is_err());
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 0);
+    assert!(bits.iter().all(|&b| b == 0));
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_set_note() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    assert_eq!(read_note(&svm, &punchcard.pubkey()), "");
+
+    let tx = Transaction::new_signed_with_payer(
+        &[set_note_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            "Spring promo, support at example.com",
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    assert_eq!(
+        read_note(&svm, &punchcard.pubkey()),
+        "Spring promo, support at example.com"
+    );
+
+    // Notes longer than 64 bytes are rejected
+    let tx = Transaction::new_signed_with_payer(
+        &[set_note_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            &"x".repeat(65),
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert!(svm.send_transaction(tx).is_err());
+}
+
+#[test]
+fn test_ping_returns_id_and_version() {
+    let (mut svm, payer) = setup();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[Instruction {
+            program_id: PROGRAM_ID,
+            accounts: vec![],
+            data: borsh::to_vec(&PunchcardInstruction::Ping).unwrap(),
+        }],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+
+    let data = meta.return_data.data;
+    assert_eq!(&data[..32], PROGRAM_ID.as_ref());
+    let version = std::str::from_utf8(&data[32..]).unwrap();
+    assert!(!version.is_empty());
+}
+
+#[test]
+fn test_create_idempotent() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    // Fresh create
+    let tx = Transaction::new_signed_with_payer(
+        &[create_idempotent_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            16,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![2])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // Already exists with a matching configuration; claims are left alone
+    svm.expire_blockhash();
+    let tx = Transaction::new_signed_with_payer(
+        &[create_idempotent_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            16,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, capacity, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(capacity, 16);
+    assert_eq!(claimed, 1);
+
+    // Already exists with a different capacity
+    let tx = Transaction::new_signed_with_payer(
+        &[create_idempotent_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            32,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(6))
+    );
+}
+
+#[test]
+fn test_claim_large_batch_compute() {
+    let (mut svm, payer) = setup();
+    let single = Keypair::new();
+    let batch = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &single.pubkey(), 1024),
+            create_ix(&payer.pubkey(), &batch.pubkey(), 1024),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &single, &batch],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // The same claim on identical cards, once with one index and once with 101,
+    // so everything but the per-index work cancels out of the difference
+    let mut compute_units = |punchcard: &Pubkey, indices: Vec<u64>| {
+        let tx = Transaction::new_signed_with_payer(
+            &[claim_ix(&payer.pubkey(), punchcard, indices)],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        svm.send_transaction(tx).unwrap().compute_units_consumed
+    };
+    let indices: Vec<u64> = (0..101).map(|i| i * 10).collect();
+    let one = compute_units(&single.pubkey(), vec![0]);
+    let many = compute_units(&batch.pubkey(), indices.clone());
+    let per_index = (many - one) / 100;
+
+    // Each extra index is decoded, checked against `capacity` and the bitset
+    // once, set without a bounds check and passed to `stamp`, which returns
+    // at once without a redemption window: a few dozen instructions. Before
+    // the upfront validation every index also went through the checked,
+    // `Result`-returning `Bits::get` and `Bits::set`; the budget leaves little
+    // room for that, where the old total of 20 000 CUs for 100 indices hid it
+    assert!(
+        per_index <= 100,
+        "claim costs {per_index} CUs per index ({one} for one, {many} for 101)"
+    );
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &batch.pubkey()).unwrap();
+    assert_eq!(claimed, 101);
+    for i in indices {
+        assert_ne!(bits[(i / 8) as usize] & (1 << (i % 8)), 0);
+    }
+
+    assert_card_consistent(&svm, &batch.pubkey());
+}
+
+#[test]
+fn test_auto_close_leaves_no_lamports() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 2)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    let rent = svm.get_account(&punchcard.pubkey()).unwrap().lamports;
+    let balance_before = svm.get_account(&payer.pubkey()).unwrap().lamports;
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let lamports = svm
+        .get_account(&punchcard.pubkey())
+        .map_or(0, |acc| acc.lamports);
+    assert_eq!(lamports, 0);
+
+    let fee = 5000;
+    let balance_after = svm.get_account(&payer.pubkey()).unwrap().lamports;
+    assert_eq!(balance_after, balance_before + rent - fee);
+}
+
+#[test]
+fn test_close_rejects_read_only_recipient() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let fee_payer = Keypair::new();
+    svm.airdrop(&fee_payer.pubkey(), 1_000_000_000).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 2)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let mut ix = claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1]);
+    ix.accounts[0] = AccountMeta::new_readonly(payer.pubkey(), true);
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&fee_payer.pubkey()),
+        &[&fee_payer, &payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::InvalidAccountData)
+    );
+}
+
+#[test]
+fn test_claim_rejects_read_only_punchcard() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 8)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    for mut ix in [
+        claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![3]),
+        claim_range_ix(&payer.pubkey(), &punchcard.pubkey(), 2, 4),
+        claim_all_ix(&payer.pubkey(), &punchcard.pubkey()),
+    ] {
+        ix.accounts[1] = AccountMeta::new_readonly(punchcard.pubkey(), false);
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        assert_eq!(
+            instruction_error(svm.send_transaction(tx)),
+            Some(InstructionError::InvalidAccountData)
+        );
+    }
+
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 0);
+}
+
+#[test]
+fn test_create_zero_capacity_fails() {
+    let (mut svm, payer) = setup();
+
+    // A zero-capacity card could never be claimed, so every create variant
+    // refuses it
+    let builders: [fn(&Pubkey, &Pubkey) -> Instruction; 4] = [
+        |payer, punchcard| create_ix(payer, punchcard, 0),
+        |payer, punchcard| create_redeemable_ix(payer, punchcard, 0, 100),
+        |payer, punchcard| create_idempotent_ix(payer, punchcard, 0),
+        |payer, punchcard| create_with_close_authority_ix(payer, punchcard, 0, payer),
+    ];
+    for build in builders {
+        let punchcard = Keypair::new();
+        let tx = Transaction::new_signed_with_payer(
+            &[build(&payer.pubkey(), &punchcard.pubkey())],
+            Some(&payer.pubkey()),
+            &[&payer, &punchcard],
+            svm.latest_blockhash(),
+        );
+        assert_eq!(
+            instruction_error(svm.send_transaction(tx)),
+            Some(InstructionError::Custom(5))
+        );
+        assert!(svm.get_account(&punchcard.pubkey()).is_none());
+    }
+}
+
+#[test]
+fn test_claim_and_transfer() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let successor = Keypair::new();
+    let third = Keypair::new();
+    svm.airdrop(&successor.pubkey(), 1_000_000_000).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_and_transfer_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            vec![1, 2],
+            &successor.pubkey(),
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (authority, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(authority, successor.pubkey());
+    assert_eq!(claimed, 2);
+    assert_eq!(bits[0], 0b0000_0110);
+
+    // A failing claim rolls back the handoff too
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_and_transfer_ix(
+            &successor.pubkey(),
+            &punchcard.pubkey(),
+            vec![3, 1],
+            &third.pubkey(),
+        )],
+        Some(&successor.pubkey()),
+        &[&successor],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(2))
+    );
+
+    let (authority, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(authority, successor.pubkey());
+    assert_eq!(claimed, 2);
+
+    // The zero key is never a valid successor
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_and_transfer_ix(
+            &successor.pubkey(),
+            &punchcard.pubkey(),
+            vec![4],
+            &Pubkey::default(),
+        )],
+        Some(&successor.pubkey()),
+        &[&successor],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(0))
+    );
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_claim_and_transfer_rejects_filling_claim() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let successor = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 4),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // Filling the card would close it and drop the handoff, so nothing happens
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_and_transfer_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            vec![2, 3],
+            &successor.pubkey(),
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(7))
+    );
+
+    let (authority, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(authority, payer.pubkey());
+    assert_eq!(claimed, 2);
+    assert_eq!(bits, vec![0b0000_0011]);
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_claim_next_dispenses_lowest_free() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 2]),
+            claim_next_ix(&payer.pubkey(), &punchcard.pubkey(), 3),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 5);
+    // 0 and 2 were already claimed, so 1, 3 and 4 are dispensed
+    assert_eq!(bits[0], 0b0001_1111);
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_claim_on_full_card_fails() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 8),
+            claim_ix(
+                &payer.pubkey(),
+                &punchcard.pubkey(),
+                vec![0, 1, 2, 3, 4, 5, 6],
+            ),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // Full cards normally close themselves, so fill this one behind the program's back
+    let mut account = svm.get_account(&punchcard.pubkey()).unwrap();
+    {
+        let card = punchcard::Punchcard::from_bytes(&mut account.data).unwrap();
+        card.header.claimed = 8;
+        card.header.update_checksum();
+    }
+    account.data[HEADER_LEN] = 0xFF;
+    svm.set_account(punchcard.pubkey(), account).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![3])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(7))
+    );
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn test_client_create_and_claim() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let (instructions, signers) =
+        punchcard::client::create_and_claim(&payer, &punchcard, 16, vec![4, 9]);
+    let tx = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&payer.pubkey()),
+        &signers,
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (authority, capacity, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(authority, payer.pubkey());
+    assert_eq!(capacity, 16);
+    assert_eq!(claimed, 2);
+    assert_eq!(bits, vec![0b0001_0000, 0b0000_0010]);
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn test_client_punchcard_view() {
+    use punchcard::client::PunchcardView;
+
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 12),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![3, 11]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let data = svm.get_account(&punchcard.pubkey()).unwrap().data;
+    let view = PunchcardView::from_account_data(&data).unwrap();
+    assert_eq!(view.authority, payer.pubkey());
+    assert_eq!(view.capacity, 12);
+    assert_eq!(view.claimed, 2);
+    assert_eq!(view.bits, vec![0b0000_1000, 0b0000_1000]);
+    assert_eq!(view.is_claimed(3), Ok(true));
+    assert_eq!(view.is_claimed(4), Ok(false));
+    assert_eq!(view.is_claimed(11), Ok(true));
+    assert!(view.is_claimed(12).is_err());
+
+    assert!(PunchcardView::from_account_data(&data[..HEADER_LEN]).is_err());
+    assert!(PunchcardView::from_account_data(&data[..8]).is_err());
+}
+
+#[test]
+fn test_close_routes_rent_to_close_recipient() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let treasury = Pubkey::new_unique();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_with_recipient_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            2,
+            &treasury,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    let rent = svm.get_account(&punchcard.pubkey()).unwrap().lamports;
+
+    let account = svm.get_account(&punchcard.pubkey()).unwrap();
+    assert_eq!(&account.data[144..176], treasury.as_ref());
+
+    // Closing without the recipient account fails
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::NotEnoughAccountKeys)
+    );
+
+    // ...as does passing some other account in its place
+    let mut ix = claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1]);
+    ix.accounts
+        .push(AccountMeta::new(Pubkey::new_unique(), false));
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(8))
+    );
+
+    // ...or passing it read-only
+    let mut ix = claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1]);
+    ix.accounts.push(AccountMeta::new_readonly(treasury, false));
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::InvalidAccountData)
+    );
+
+    let mut ix = claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1]);
+    ix.accounts.push(AccountMeta::new(treasury, false));
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    assert_eq!(svm.get_account(&treasury).unwrap().lamports, rent);
+}
+
+#[test]
+fn test_reset_and_resize_grows() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 8),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 3, 5]),
+            reset_and_resize_ix(&payer.pubkey(), &punchcard.pubkey(), 100),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, capacity, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(capacity, 100);
+    assert_eq!(claimed, 0);
+    assert!(bits.iter().all(|&b| b == 0));
+
+    let account = svm.get_account(&punchcard.pubkey()).unwrap();
+    assert_eq!(account.data.len(), HEADER_LEN + 13);
+    assert_eq!(
+        account.lamports,
+        svm.minimum_balance_for_rent_exemption(HEADER_LEN + 13)
+    );
+
+    // The new range is claimable
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 99])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_reset_and_resize_shrinks() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 800),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1, 2, 700]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    let rent_before = svm.get_account(&punchcard.pubkey()).unwrap().lamports;
+    let payer_before = svm.get_balance(&payer.pubkey()).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[reset_and_resize_ix(&payer.pubkey(), &punchcard.pubkey(), 4)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, capacity, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(capacity, 4);
+    assert_eq!(claimed, 0);
+    assert_eq!(bits, [0]);
+
+    let rent_after = svm.minimum_balance_for_rent_exemption(HEADER_LEN + 1);
+    let account = svm.get_account(&punchcard.pubkey()).unwrap();
+    assert_eq!(account.data.len(), HEADER_LEN + 1);
+    assert_eq!(account.lamports, rent_after);
+    // Excess rent comes back to the authority, less the transaction fee
+    assert_eq!(
+        svm.get_balance(&payer.pubkey()).unwrap(),
+        payer_before + (rent_before - rent_after) - 5000
+    );
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[cfg(feature = "compute-guard")]
+#[test]
+fn test_claim_fails_early_on_small_compute_budget() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 1024)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // SetComputeUnitLimit, encoded by hand to avoid pulling in the compute
+    // budget interface crate
+    let mut limit_data = vec![2];
+    limit_data.extend_from_slice(&60_000u32.to_le_bytes());
+    let set_limit = Instruction {
+        program_id: Pubkey::from_str_const("ComputeBudget111111111111111111111111111111"),
+        accounts: vec![],
+        data: limit_data,
+    };
+
+    let indices: Vec<u64> = (0..1000).collect();
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            set_limit,
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), indices),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(9))
+    );
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_create_with_extra_lamports() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let ix = create_accounts_ix(
+        &payer.pubkey(),
+        &punchcard.pubkey(),
+        PunchcardInstruction::Create {
+            capacity: 8,
+            redemption_window: 0,
+            close_recipient: [0; 32],
+            extra_lamports: 1_000_000,
+        },
+    );
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let rent = svm.minimum_balance_for_rent_exemption(HEADER_LEN + 1);
+    assert_eq!(
+        svm.get_balance(&punchcard.pubkey()).unwrap(),
+        rent + 1_000_000
+    );
+
+    // More than the payer holds
+    let punchcard = Keypair::new();
+    let ix = create_accounts_ix(
+        &payer.pubkey(),
+        &punchcard.pubkey(),
+        PunchcardInstruction::Create {
+            capacity: 8,
+            redemption_window: 0,
+            close_recipient: [0; 32],
+            extra_lamports: 100_000_000_000,
+        },
+    );
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::InsufficientFunds)
+    );
+}
+
+#[test]
+fn test_total_claims_survives_reset() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 8),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1, 2]),
+            reset_and_resize_ix(&payer.pubkey(), &punchcard.pubkey(), 8),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+    let data = svm.get_account(&punchcard.pubkey()).unwrap().data;
+    let total_claims = u64::from_le_bytes(data[176..184].try_into().unwrap());
+    assert_eq!(total_claims, 4);
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn test_client_decodes_return_data() {
+    use punchcard::client::{decode_claim_expired, decode_diagnose, decode_ping};
+
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_redeemable_ix(&payer.pubkey(), &punchcard.pubkey(), 8, 100),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![2]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let ping = Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![],
+        data: borsh::to_vec(&PunchcardInstruction::Ping).unwrap(),
+    };
+    let mut returned = Vec::new();
+    for ix in [
+        ping,
+        claim_expired_ix(&punchcard.pubkey(), 2),
+        diagnose_ix(&punchcard.pubkey()),
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        returned.push(svm.send_transaction(tx).unwrap().return_data.data);
+    }
+
+    let (id, version) = decode_ping(&returned[0]).unwrap();
+    assert_eq!(id, PROGRAM_ID.to_bytes());
+    assert_eq!(version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(decode_claim_expired(&returned[1]), Some(false));
+    assert_eq!(decode_diagnose(&returned[2]), Some(0));
+}
+
+#[test]
+fn test_touch_claim_is_idempotent() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 8)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[touch_claim_ix(&payer.pubkey(), &punchcard.pubkey(), 5)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert_eq!(meta.return_data.data, vec![1]);
+
+    // A repeat touch succeeds without claiming again
+    svm.expire_blockhash();
+    let tx = Transaction::new_signed_with_payer(
+        &[touch_claim_ix(&payer.pubkey(), &punchcard.pubkey(), 5)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert_eq!(meta.return_data.data, vec![0]);
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+    assert_eq!(bits, vec![0b0010_0000]);
+
+    let tx = Transaction::new_signed_with_payer(
+        &[touch_claim_ix(&payer.pubkey(), &punchcard.pubkey(), 8)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(1))
+    );
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn test_created_card_is_rent_exempt() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_redeemable_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            300,
+            10,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let lamports = svm.get_balance(&punchcard.pubkey()).unwrap();
+    let rent = svm.get_sysvar();
+    assert!(punchcard::client::is_rent_exempt(
+        lamports, 300, 10, false, &rent
+    ));
+    assert!(!punchcard::client::is_rent_exempt(
+        lamports - 1,
+        300,
+        10,
+        false,
+        &rent
+    ));
+}
+
+#[test]
+fn test_claim_random_dispenses_distinct_indices() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 4)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let mut picked = Vec::new();
+    for seed in 0..4 {
+        let tx = Transaction::new_signed_with_payer(
+            &[claim_random_ix(&payer.pubkey(), &punchcard.pubkey(), seed)],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        let meta = svm.send_transaction(tx).unwrap();
+        picked.push(u64::from_le_bytes(
+            meta.return_data.data.try_into().unwrap(),
+        ));
+    }
+    picked.sort();
+    assert_eq!(picked, [0, 1, 2, 3]);
+
+    // The last pick filled the card and closed it, so nothing is left to claim
+    assert!(
+        svm.get_account(&punchcard.pubkey())
+            .is_none_or(|a| a.data.is_empty())
+    );
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_random_ix(&payer.pubkey(), &punchcard.pubkey(), 4)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert!(svm.send_transaction(tx).is_err());
+}
+
+#[test]
+fn test_claim_rejects_bad_magic() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 8)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let mut account = svm.get_account(&punchcard.pubkey()).unwrap();
+    assert_eq!(&account.data[..8], b"PUNCHCRD");
+    account.data[..8].copy_from_slice(b"NOTACARD");
+    svm.set_account(punchcard.pubkey(), account).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(11))
+    );
+}
+
+#[test]
+fn test_claim_percent_half_of_remaining() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), (0..8).collect()),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_percent_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            5_000,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+
+    let mut expected = 4u64.to_le_bytes().to_vec();
+    for index in 8u64..12 {
+        expected.extend_from_slice(&index.to_le_bytes());
+    }
+    assert_eq!(meta.return_data.data, expected);
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 12);
+    assert_eq!(bits, vec![0xFF, 0b0000_1111]);
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_percent_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            10_001,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::InvalidInstructionData)
+    );
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_derived_claims_stop_at_max_claim_indices() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let max = punchcard::MAX_CLAIM_INDICES as u64;
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 1024)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // The program picks these indices, and claims at most the limit of them
+    for (ix, claimed) in [
+        (
+            claim_next_ix(&payer.pubkey(), &punchcard.pubkey(), max + 1),
+            max,
+        ),
+        (
+            claim_percent_ix(&payer.pubkey(), &punchcard.pubkey(), 10_000),
+            2 * max,
+        ),
+        // A quarter of the 512 left
+        (
+            claim_percent_ix(&payer.pubkey(), &punchcard.pubkey(), 2_500),
+            2 * max + 128,
+        ),
+    ] {
+        svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        svm.send_transaction(tx).unwrap();
+
+        let (_, _, actual, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+        assert_eq!(actual, claimed);
+    }
+
+    let (_, _, _, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert!(bits[..80].iter().all(|&byte| byte == 0xFF));
+    assert!(bits[80..].iter().all(|&byte| byte == 0));
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_range_claims_respect_max_claim_indices() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let max = punchcard::MAX_CLAIM_INDICES as u64;
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 1024)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    for ix in [
+        claim_range_ix(&payer.pubkey(), &punchcard.pubkey(), 0, max + 1),
+        claim_all_ix(&payer.pubkey(), &punchcard.pubkey()),
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        assert_eq!(
+            instruction_error(svm.send_transaction(tx)),
+            Some(InstructionError::Custom(96))
+        );
+    }
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_range_ix(&payer.pubkey(), &punchcard.pubkey(), 0, max)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, max);
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_claim_percent_all_remaining_closes() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 10),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 5]),
+            claim_percent_ix(&payer.pubkey(), &punchcard.pubkey(), 10_000),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    assert!(
+        svm.get_account(&punchcard.pubkey())
+            .is_none_or(|account| account.data.is_empty())
+    );
+}
+
+#[test]
+fn test_close_refunds_contributors() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let alice = Keypair::new();
+    let bob = Keypair::new();
+    svm.airdrop(&alice.pubkey(), 1_000_000_000).unwrap();
+    svm.airdrop(&bob.pubkey(), 1_000_000_000).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 4),
+            contribute_ix(&alice.pubkey(), &punchcard.pubkey(), 2_000_000),
+            contribute_ix(&bob.pubkey(), &punchcard.pubkey(), 5_000_000),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard, &alice, &bob],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let rent = svm.minimum_balance_for_rent_exemption(HEADER_LEN + 1);
+    assert_eq!(
+        svm.get_balance(&punchcard.pubkey()).unwrap(),
+        rent + 7_000_000
+    );
+    let alice_before = svm.get_balance(&alice.pubkey()).unwrap();
+    let bob_before = svm.get_balance(&bob.pubkey()).unwrap();
+    let payer_before = svm.get_balance(&payer.pubkey()).unwrap();
+
+    // Closing needs every contributor, in header order
+    let mut ix = claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1, 2, 3]);
+    ix.accounts.push(AccountMeta::new(bob.pubkey(), false));
+    ix.accounts.push(AccountMeta::new(alice.pubkey(), false));
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(13))
+    );
+
+    let mut ix = claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1, 2, 3]);
+    ix.accounts.push(AccountMeta::new(alice.pubkey(), false));
+    ix.accounts.push(AccountMeta::new(bob.pubkey(), false));
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    assert_eq!(
+        svm.get_balance(&alice.pubkey()).unwrap(),
+        alice_before + 2_000_000
+    );
+    assert_eq!(
+        svm.get_balance(&bob.pubkey()).unwrap(),
+        bob_before + 5_000_000
+    );
+    // The authority gets the rent back, less the transaction fee
+    assert_eq!(
+        svm.get_balance(&payer.pubkey()).unwrap(),
+        payer_before + rent - 5000
+    );
+}
+
+#[test]
+fn test_contribute_rejects_dust() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 4)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let minimum = svm.minimum_balance_for_rent_exemption(0);
+    let tx = Transaction::new_signed_with_payer(
+        &[contribute_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            minimum - 1,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(64))
+    );
+
+    let tx = Transaction::new_signed_with_payer(
+        &[contribute_ix(&payer.pubkey(), &punchcard.pubkey(), minimum)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+}
+
+#[test]
+fn test_reset_and_resize_keeps_contributions() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let alice = Keypair::new();
+    svm.airdrop(&alice.pubkey(), 1_000_000_000).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 800),
+            contribute_ix(&alice.pubkey(), &punchcard.pubkey(), 2_000_000),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard, &alice],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[reset_and_resize_ix(&payer.pubkey(), &punchcard.pubkey(), 4)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // Only the excess rent goes back; the deposit waits for the close
+    let rent = svm.minimum_balance_for_rent_exemption(HEADER_LEN + 1);
+    assert_eq!(
+        svm.get_balance(&punchcard.pubkey()).unwrap(),
+        rent + 2_000_000
+    );
+
+    let alice_before = svm.get_balance(&alice.pubkey()).unwrap();
+    let mut ix = claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1, 2, 3]);
+    ix.accounts.push(AccountMeta::new(alice.pubkey(), false));
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    assert_eq!(
+        svm.get_balance(&alice.pubkey()).unwrap(),
+        alice_before + 2_000_000
+    );
+}
+
+#[test]
+fn test_require_claimed_gates_cpi() {
+    let (mut svm, payer) = setup();
+    let gate = Pubkey::new_unique();
+    svm.add_program_from_file(gate, "target/deploy/gate.so")
+        .expect("Run `cargo build-sbf` first");
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![3]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let gate_ix = |index: u64| Instruction {
+        program_id: gate,
+        accounts: vec![
+            AccountMeta::new_readonly(PROGRAM_ID, false),
+            AccountMeta::new_readonly(punchcard.pubkey(), false),
+        ],
+        data: index.to_le_bytes().to_vec(),
+    };
+
+    let tx = Transaction::new_signed_with_payer(
+        &[gate_ix(3)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert_eq!(meta.return_data.data, b"open");
+
+    for (index, code) in [(4, 3), (16, 1)] {
+        let tx = Transaction::new_signed_with_payer(
+            &[gate_ix(index)],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        assert_eq!(
+            instruction_error(svm.send_transaction(tx)),
+            Some(InstructionError::Custom(code))
+        );
+    }
+}
+
+#[test]
+fn test_create_rejects_pda_punchcard() {
+    let (mut svm, payer) = setup();
+    let (pda, _) = Pubkey::find_program_address(&[b"card"], &PROGRAM_ID);
+
+    let mut ix = create_ix(&payer.pubkey(), &pda, 8);
+    ix.accounts[1].is_signer = false;
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(14))
+    );
+}
+
+#[test]
+fn test_claim_until_rejects_after_valid_slot() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    svm.warp_to_slot(100);
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 8),
+            claim_until_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1], 100),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    svm.warp_to_slot(101);
+    svm.expire_blockhash();
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_until_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            vec![2],
+            100,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(20))
+    );
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+    assert_eq!(bits, vec![0b0000_0010]);
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_create_inverted_starts_full() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let ix = create_accounts_ix(
+        &payer.pubkey(),
+        &punchcard.pubkey(),
+        PunchcardInstruction::CreateInverted {
+            capacity: 10,
+            redemption_window: 0,
+            close_recipient: [0; 32],
+        },
+    );
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (authority, capacity, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(authority, payer.pubkey());
+    assert_eq!(capacity, 10);
+    assert_eq!(claimed, 10);
+    assert_eq!(bits, vec![0xFF, 0b0000_0011]);
+    assert_card_consistent(&svm, &punchcard.pubkey());
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(7))
+    );
+}
+
+#[test]
+fn test_transfer_authority() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let successor = Keypair::new();
+    svm.airdrop(&successor.pubkey(), 1_000_000_000).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![2]),
+            transfer_authority_ix(&payer.pubkey(), &punchcard.pubkey(), &successor.pubkey()),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (authority, capacity, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(authority, successor.pubkey());
+    assert_eq!(capacity, 16);
+    assert_eq!(claimed, 1);
+    assert_eq!(bits, vec![0b0000_0100, 0]);
+
+    // The old authority is locked out...
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![3])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(0))
+    );
+
+    // ...and the new one can claim
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&successor.pubkey(), &punchcard.pubkey(), vec![3])],
+        Some(&successor.pubkey()),
+        &[&successor],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // Handing the card to nobody is refused
+    let tx = Transaction::new_signed_with_payer(
+        &[transfer_authority_ix(
+            &successor.pubkey(),
+            &punchcard.pubkey(),
+            &Pubkey::default(),
+        )],
+        Some(&successor.pubkey()),
+        &[&successor],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(0))
+    );
+
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_close_partially_claimed_card() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let stranger = Keypair::new();
+    svm.airdrop(&stranger.pubkey(), 1_000_000_000).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 32),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1, 20]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[close_ix(&stranger.pubkey(), &punchcard.pubkey())],
+        Some(&stranger.pubkey()),
+        &[&stranger],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(0))
+    );
+
+    let rent = svm.get_balance(&punchcard.pubkey()).unwrap();
+    let payer_before = svm.get_balance(&payer.pubkey()).unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[close_ix(&payer.pubkey(), &punchcard.pubkey())],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    assert!(
+        svm.get_account(&punchcard.pubkey())
+            .is_none_or(|account| account.data.is_empty())
+    );
+    assert_eq!(
+        svm.get_balance(&payer.pubkey()).unwrap(),
+        payer_before + rent - 5000
+    );
+}
+
+#[test]
+fn test_unclaim() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5]),
+            unclaim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 0);
+    assert_eq!(bits, vec![0, 0]);
+    assert_card_consistent(&svm, &punchcard.pubkey());
+
+    let tx = Transaction::new_signed_with_payer(
+        &[unclaim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(3))
+    );
+}
+
+#[test]
+fn test_claim_range() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 32),
+            claim_range_ix(&payer.pubkey(), &punchcard.pubkey(), 5, 19),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 14);
+    assert_eq!(bits, vec![0b1110_0000, 0xFF, 0b0000_0111, 0]);
+    assert_card_consistent(&svm, &punchcard.pubkey());
+
+    for (start, end, error) in [
+        (18, 24, InstructionError::Custom(2)),
+        (20, 33, InstructionError::Custom(1)),
+        (24, 20, InstructionError::InvalidInstructionData),
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[claim_range_ix(
+                &payer.pubkey(),
+                &punchcard.pubkey(),
+                start,
+                end,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        assert_eq!(instruction_error(svm.send_transaction(tx)), Some(error));
+    }
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 14);
+}
+
+#[test]
+fn test_recount_repairs_claimed() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 12),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1, 2, 11]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // Drift the counter past capacity and set a padding bit, as a bad migration might
+    let mut account = svm.get_account(&punchcard.pubkey()).unwrap();
+    {
+        let card = punchcard::Punchcard::from_bytes_unchecked_counts(&mut account.data).unwrap();
+        card.header.claimed = 30;
+        card.header.update_checksum();
+    }
+    account.data[HEADER_LEN + 1] |= 0b0100_0000;
+    svm.set_account(punchcard.pubkey(), account).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[recount_ix(&payer.pubkey(), &punchcard.pubkey())],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 3);
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_empty_claim_fails() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::InvalidInstructionData)
+    );
+}
+
+#[test]
+fn test_claim_duplicate_index() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            vec![1, 2, 1],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(15))
+    );
+
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 0);
+}
+
+#[test]
+fn test_create_rejects_existing_account() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![3]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::AccountAlreadyInitialized)
+    );
+
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+
+    // A fresh account someone has already funded is refused too
+    let prefunded = Keypair::new();
+    svm.airdrop(&prefunded.pubkey(), 1_000_000).unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &prefunded.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &prefunded],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::AccountAlreadyInitialized)
+    );
+}
+
+#[test]
+fn test_create_rejects_wrong_system_program() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let mut ix = create_ix(&payer.pubkey(), &punchcard.pubkey(), 16);
+    ix.accounts[2] = AccountMeta::new_readonly(PROGRAM_ID, false);
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::IncorrectProgramId)
+    );
+    assert!(svm.get_account(&punchcard.pubkey()).is_none());
+}
+
+#[test]
+fn test_create_pda() {
+    let (mut svm, payer) = setup();
+    let seed = [7; 32];
+    let (punchcard, bump) = Pubkey::find_program_address(
+        &[
+            punchcard::PUNCHCARD_SEED,
+            payer.pubkey().as_ref(),
+            &seed[..],
+        ],
+        &PROGRAM_ID,
+    );
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_pda_ix(&payer.pubkey(), &punchcard, 16, seed),
+            claim_ix(&payer.pubkey(), &punchcard, vec![0, 3]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (authority, capacity, claimed, bits) = read_punchcard(&svm, &punchcard).unwrap();
+    assert_eq!(authority, payer.pubkey());
+    assert_eq!(capacity, 16);
+    assert_eq!(claimed, 2);
+    assert_eq!(bits, vec![0b0000_1001, 0]);
+    let bump_at = core::mem::offset_of!(punchcard::PunchcardHeader, bump);
+    assert_eq!(svm.get_account(&punchcard).unwrap().data[bump_at], bump);
+
+    // The address must be the one derived from the seed in the instruction
+    let tx = Transaction::new_signed_with_payer(
+        &[create_pda_ix(&payer.pubkey(), &punchcard, 16, [8; 32])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::InvalidSeeds)
+    );
+}
+
+#[test]
+fn test_resize_keeps_claims() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1, 5, 15]),
+            resize_ix(&payer.pubkey(), &punchcard.pubkey(), 64),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let account = svm.get_account(&punchcard.pubkey()).unwrap();
+    assert_eq!(account.data.len(), HEADER_LEN + 8);
+    assert!(account.lamports >= svm.minimum_balance_for_rent_exemption(HEADER_LEN + 8));
+    let (_, capacity, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(capacity, 64);
+    assert_eq!(claimed, 3);
+    assert_eq!(bits, vec![0b0010_0010, 0b1000_0000, 0, 0, 0, 0, 0, 0]);
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![16, 63])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    assert_card_consistent(&svm, &punchcard.pubkey());
+
+    let tx = Transaction::new_signed_with_payer(
+        &[resize_ix(&payer.pubkey(), &punchcard.pubkey(), 32)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(16))
+    );
+}
+
+#[test]
+fn test_unknown_version_is_rejected() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let version_at = core::mem::offset_of!(punchcard::PunchcardHeader, version);
+    let mut account = svm.get_account(&punchcard.pubkey()).unwrap();
+    assert_eq!(account.data[version_at], punchcard::CURRENT_VERSION);
+    account.data[version_at] = punchcard::CURRENT_VERSION + 1;
+    svm.set_account(punchcard.pubkey(), account).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(17))
+    );
+}
+
+#[test]
+fn test_claims_reject_a_drifted_counter() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 8),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // Rewrite the counter with a matching checksum, so loading the card succeeds
+    let set_claimed = |svm: &mut LiteSVM, claimed: u64| {
+        let mut account = svm.get_account(&punchcard.pubkey()).unwrap();
+        let card = punchcard::Punchcard::from_bytes(&mut account.data).unwrap();
+        card.header.claimed = claimed;
+        card.header.update_checksum();
+        svm.set_account(punchcard.pubkey(), account).unwrap();
+    };
+
+    // One bit is set but the counter says seven, so two more claims would pass capacity
+    set_claimed(&mut svm, 7);
+    for (ix, error) in [
+        (
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1, 2]),
+            InstructionError::InvalidAccountData,
+        ),
+        (
+            claim_range_ix(&payer.pubkey(), &punchcard.pubkey(), 1, 3),
+            InstructionError::InvalidAccountData,
+        ),
+        (
+            claim_all_ix(&payer.pubkey(), &punchcard.pubkey()),
+            InstructionError::InvalidAccountData,
+        ),
+        (
+            toggle_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1, 2]),
+            InstructionError::InvalidAccountData,
+        ),
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        assert_eq!(instruction_error(svm.send_transaction(tx)), Some(error));
+    }
+
+    // And here it says none, so reversing the one claim would wrap
+    set_claimed(&mut svm, 0);
+    for ix in [
+        unclaim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0]),
+        toggle_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0]),
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        assert_eq!(
+            instruction_error(svm.send_transaction(tx)),
+            Some(InstructionError::ArithmeticOverflow)
+        );
+    }
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 0);
+    assert_eq!(bits, [0b0000_0001]);
+}
+
+#[test]
+fn test_migrate_legacy_card() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 20)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // Rewrite it as the program's first release laid cards out: authority,
+    // capacity, claimed, bits, with no magic and only that header's rent
+    let mut account = svm.get_account(&punchcard.pubkey()).unwrap();
+    let mut legacy = payer.pubkey().to_bytes().to_vec();
+    legacy.extend_from_slice(&20u64.to_le_bytes());
+    legacy.extend_from_slice(&2u64.to_le_bytes());
+    legacy.extend_from_slice(&[0b0001_0001, 0, 0]);
+    assert_eq!(legacy.len(), punchcard::LEGACY_HEADER_LEN + 3);
+    account.lamports = svm.minimum_balance_for_rent_exemption(legacy.len());
+    account.data = legacy;
+    svm.set_account(punchcard.pubkey(), account).unwrap();
+
+    // Too short to hold a current header, let alone its magic
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::AccountDataTooSmall)
+    );
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            migrate_ix(&payer.pubkey(), &punchcard.pubkey()),
+            // A second run finds nothing to do
+            migrate_ix(&payer.pubkey(), &punchcard.pubkey()),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let account = svm.get_account(&punchcard.pubkey()).unwrap();
+    assert_eq!(account.data.len(), HEADER_LEN + 3);
+    assert_eq!(
+        account.lamports,
+        svm.minimum_balance_for_rent_exemption(HEADER_LEN + 3)
+    );
+    let (authority, capacity, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(authority, payer.pubkey());
+    assert_eq!(capacity, 20);
+    assert_eq!(claimed, 3);
+    assert_eq!(bits, [0b0001_0011, 0, 0]);
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_create_named() {
+    let (mut svm, payer) = setup();
+    let longest = "x".repeat(64);
+
+    for name in ["Summer 2026", longest.as_str()] {
+        let punchcard = Keypair::new();
+        let tx = Transaction::new_signed_with_payer(
+            &[create_accounts_ix(
+                &payer.pubkey(),
+                &punchcard.pubkey(),
+                PunchcardInstruction::CreateNamed {
+                    capacity: 16,
+                    redemption_window: 0,
+                    close_recipient: [0; 32],
+                    name: name.to_string(),
+                },
+            )],
+            Some(&payer.pubkey()),
+            &[&payer, &punchcard],
+            svm.latest_blockhash(),
+        );
+        svm.send_transaction(tx).unwrap();
+        assert_eq!(read_note(&svm, &punchcard.pubkey()), name);
+    }
+
+    let punchcard = Keypair::new();
+    let tx = Transaction::new_signed_with_payer(
+        &[create_accounts_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            PunchcardInstruction::CreateNamed {
+                capacity: 16,
+                redemption_window: 0,
+                close_recipient: [0; 32],
+                name: "x".repeat(65),
+            },
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::InvalidInstructionData)
+    );
+}
+
+#[test]
+fn test_claims_fail_after_expiry() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_accounts_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            PunchcardInstruction::CreateExpiring {
+                capacity: 16,
+                redemption_window: 0,
+                close_recipient: [0; 32],
+                expiry_slot: 100,
+            },
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    // Claims are still accepted in the expiry slot itself
+    svm.warp_to_slot(100);
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    svm.warp_to_slot(101);
+    for ix in [
+        claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![2]),
+        claim_range_ix(&payer.pubkey(), &punchcard.pubkey(), 2, 4),
+        claim_all_ix(&payer.pubkey(), &punchcard.pubkey()),
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        assert_eq!(
+            instruction_error(svm.send_transaction(tx)),
+            Some(InstructionError::Custom(18))
+        );
+    }
+
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+}
+
+#[test]
+fn test_claim_logs() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1, 2])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert!(
+        meta.logs
+            .contains(&"Program log: punchcard: claimed=2 closed=false".to_string()),
+        "{:?}",
+        meta.logs
+    );
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_range_ix(&payer.pubkey(), &punchcard.pubkey(), 3, 16)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert!(
+        meta.logs
+            .contains(&"Program log: punchcard: claimed=1 closed=true".to_string()),
+        "{:?}",
+        meta.logs
+    );
+}
+
+#[test]
+fn test_claim_returns_claimed_count() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 8),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            vec![4, 6, 7],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    let (_, capacity, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(
+        punchcard::return_data::decode_claim(&meta.return_data.data),
+        Some((claimed, capacity))
+    );
+    assert_eq!(claimed, 5);
+
+    // Still reported when the claim fills and closes the card
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            vec![2, 3, 5],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert_eq!(
+        punchcard::return_data::decode_claim(&meta.return_data.data),
+        Some((8, 8))
+    );
+}
+
+#[test]
+fn test_query_returns_header_fields() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![3, 9]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    let before = svm.get_account(&punchcard.pubkey()).unwrap();
+
+    // Any fee payer can query; the card is read-only and needs no signer
+    let reader = Keypair::new();
+    svm.airdrop(&reader.pubkey(), 1_000_000_000).unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[query_ix(&punchcard.pubkey())],
+        Some(&reader.pubkey()),
+        &[&reader],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+
+    assert_eq!(
+        punchcard::return_data::decode_query(&meta.return_data.data),
+        Some((payer.pubkey().to_bytes(), 16, 2))
+    );
+    assert_eq!(svm.get_account(&punchcard.pubkey()).unwrap(), before);
+}
+
+#[test]
+fn test_close_authority_must_sign_to_close() {
+    let (mut svm, payer) = setup();
+    let treasury = Keypair::new();
+    svm.airdrop(&treasury.pubkey(), 1_000_000_000).unwrap();
+
+    // Filling the card without the close authority claims but leaves it open
+    let punchcard = Keypair::new();
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_with_close_authority_ix(
+                &payer.pubkey(),
+                &punchcard.pubkey(),
+                2,
+                &treasury.pubkey(),
+            ),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert!(
+        meta.logs
+            .contains(&"Program log: punchcard: claimed=2 closed=false".to_string())
+    );
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 2);
+    let rent = svm.get_account(&punchcard.pubkey()).unwrap().lamports;
+
+    // Only the close authority can close it, and the rent goes to it
+    let tx = Transaction::new_signed_with_payer(
+        &[close_ix(&payer.pubkey(), &punchcard.pubkey())],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(0))
+    );
+
+    let treasury_before = svm.get_account(&treasury.pubkey()).unwrap().lamports;
+    let tx = Transaction::new_signed_with_payer(
+        &[close_ix(&treasury.pubkey(), &punchcard.pubkey())],
+        Some(&payer.pubkey()),
+        &[&payer, &treasury],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    assert!(
+        svm.get_account(&punchcard.pubkey())
+            .is_none_or(|acc| acc.lamports == 0)
+    );
+    assert_eq!(
+        svm.get_account(&treasury.pubkey()).unwrap().lamports,
+        treasury_before + rent
+    );
+
+    // A claim the close authority co-signs closes the card as it fills
+    let punchcard = Keypair::new();
+    let mut claim = claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1]);
+    claim
+        .accounts
+        .push(AccountMeta::new(treasury.pubkey(), true));
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_with_close_authority_ix(
+                &payer.pubkey(),
+                &punchcard.pubkey(),
+                2,
+                &treasury.pubkey(),
+            ),
+            claim,
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard, &treasury],
+        svm.latest_blockhash(),
+    );
+    let meta = svm.send_transaction(tx).unwrap();
+    assert!(
+        meta.logs
+            .contains(&"Program log: punchcard: claimed=2 closed=true".to_string())
+    );
+    assert!(
+        svm.get_account(&punchcard.pubkey())
+            .is_none_or(|acc| acc.lamports == 0)
+    );
+    assert_eq!(
+        svm.get_account(&treasury.pubkey()).unwrap().lamports,
+        treasury_before + 2 * rent
+    );
+}
+
+#[test]
+fn test_zero_close_authority_closes_with_claim() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_with_close_authority_ix(
+                &payer.pubkey(),
+                &punchcard.pubkey(),
+                2,
+                &Pubkey::default(),
+            ),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0, 1]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    assert!(
+        svm.get_account(&punchcard.pubkey())
+            .is_none_or(|acc| acc.lamports == 0)
+    );
+}
+
+#[test]
+fn test_paused_card_refuses_claims() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            set_paused_ix(&payer.pubkey(), &punchcard.pubkey(), true),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    for ix in [
+        claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1]),
+        claim_range_ix(&payer.pubkey(), &punchcard.pubkey(), 2, 4),
+        claim_all_ix(&payer.pubkey(), &punchcard.pubkey()),
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&payer.pubkey()),
+            &[&payer],
+            svm.latest_blockhash(),
+        );
+        assert_eq!(
+            instruction_error(svm.send_transaction(tx)),
+            Some(InstructionError::Custom(19))
+        );
+    }
+
+    // Only the authority can lift the pause
+    let other = Keypair::new();
+    svm.airdrop(&other.pubkey(), 1_000_000_000).unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[set_paused_ix(&other.pubkey(), &punchcard.pubkey(), false)],
+        Some(&other.pubkey()),
+        &[&other],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(0))
+    );
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            set_paused_ix(&payer.pubkey(), &punchcard.pubkey(), false),
+            claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![1]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+    assert_card_consistent(&svm, &punchcard.pubkey());
+}
+
+#[test]
+fn test_multi_claim_updates_every_card() {
+    let (mut svm, payer) = setup();
+    let first = Keypair::new();
+    let second = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &first.pubkey(), 16),
+            create_ix(&payer.pubkey(), &second.pubkey(), 2),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &first, &second],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    let cards = [first.pubkey(), second.pubkey()];
+
+    // One bad sub-claim fails the whole instruction
+    let tx = Transaction::new_signed_with_payer(
+        &[multi_claim_ix(
+            &payer.pubkey(),
+            &cards,
+            vec![(0, vec![1]), (1, vec![2])],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(1))
+    );
+    let (_, _, claimed, _) = read_punchcard(&svm, &first.pubkey()).unwrap();
+    assert_eq!(claimed, 0);
+
+    // The second card fills and closes on its own
+    let tx = Transaction::new_signed_with_payer(
+        &[multi_claim_ix(
+            &payer.pubkey(),
+            &cards,
+            vec![(0, vec![1, 5]), (1, vec![0, 1]), (0, vec![7])],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
 
-    Some((authority, capacity, claimed, bits))
-}
+    let (_, _, claimed, bits) = read_punchcard(&svm, &first.pubkey()).unwrap();
+    assert_eq!(claimed, 3);
+    assert_eq!(bits, vec![0b1010_0010, 0]);
+    assert!(
+        svm.get_account(&second.pubkey())
+            .is_none_or(|acc| acc.lamports == 0)
+    );
 
-fn setup() -> (LiteSVM, Keypair) {
-    let mut svm = LiteSVM::new();
-    svm.add_program_from_file(PROGRAM_ID, "target/deploy/punchcard.so")
-        .expect("Run `cargo build-sbf` first");
-    let payer = Keypair::new();
-    svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
-    (svm, payer)
+    let tx = Transaction::new_signed_with_payer(
+        &[multi_claim_ix(
+            &payer.pubkey(),
+            &cards[..1],
+            vec![(1, vec![0])],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::NotEnoughAccountKeys)
+    );
 }
 
 #[test]
-fn test_create_punchcard() {
+fn test_delegate_claims_until_removed() {
     let (mut svm, payer) = setup();
     let punchcard = Keypair::new();
+    let agent = Keypair::new();
+    svm.airdrop(&agent.pubkey(), 1_000_000_000).unwrap();
 
     let tx = Transaction::new_signed_with_payer(
-        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            add_delegate_ix(&payer.pubkey(), &punchcard.pubkey(), &agent.pubkey()),
+        ],
         Some(&payer.pubkey()),
         &[&payer, &punchcard],
         svm.latest_blockhash(),
     );
     svm.send_transaction(tx).unwrap();
 
-    let (authority, capacity, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
-    assert_eq!(authority, payer.pubkey());
-    assert_eq!(capacity, 16);
-    assert_eq!(claimed, 0);
-    assert_eq!(bits.len(), 2);
-    assert!(bits.iter().all(|&b| b == 0));
-}
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&agent.pubkey(), &punchcard.pubkey(), vec![4])],
+        Some(&agent.pubkey()),
+        &[&agent],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
 
-#[test]
-fn test_claim_single_index() {
-    let (mut svm, payer) = setup();
-    let punchcard = Keypair::new();
+    // Delegates can claim but not manage delegates
+    let tx = Transaction::new_signed_with_payer(
+        &[add_delegate_ix(
+            &agent.pubkey(),
+            &punchcard.pubkey(),
+            &Pubkey::new_unique(),
+        )],
+        Some(&agent.pubkey()),
+        &[&agent],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(0))
+    );
 
+    // The agent holds one of the four slots
     let tx = Transaction::new_signed_with_payer(
-        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        &[
+            add_delegate_ix(&payer.pubkey(), &punchcard.pubkey(), &Pubkey::new_unique()),
+            add_delegate_ix(&payer.pubkey(), &punchcard.pubkey(), &Pubkey::new_unique()),
+            add_delegate_ix(&payer.pubkey(), &punchcard.pubkey(), &Pubkey::new_unique()),
+            add_delegate_ix(&payer.pubkey(), &punchcard.pubkey(), &Pubkey::new_unique()),
+        ],
         Some(&payer.pubkey()),
-        &[&payer, &punchcard],
+        &[&payer],
         svm.latest_blockhash(),
     );
-    svm.send_transaction(tx).unwrap();
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(97))
+    );
 
     let tx = Transaction::new_signed_with_payer(
-        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5])],
+        &[remove_delegate_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            &agent.pubkey(),
+        )],
         Some(&payer.pubkey()),
         &[&payer],
         svm.latest_blockhash(),
     );
     svm.send_transaction(tx).unwrap();
 
-    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&agent.pubkey(), &punchcard.pubkey(), vec![5])],
+        Some(&agent.pubkey()),
+        &[&agent],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(0))
+    );
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
     assert_eq!(claimed, 1);
-    assert_eq!(bits[0] & (1 << 5), 1 << 5);
 }
 
 #[test]
-fn test_claim_multiple_indices() {
+fn test_delegate_fill_leaves_card_open() {
     let (mut svm, payer) = setup();
     let punchcard = Keypair::new();
+    let agent = Keypair::new();
+    svm.airdrop(&agent.pubkey(), 1_000_000_000).unwrap();
 
     let tx = Transaction::new_signed_with_payer(
-        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 2),
+            add_delegate_ix(&payer.pubkey(), &punchcard.pubkey(), &agent.pubkey()),
+        ],
         Some(&payer.pubkey()),
         &[&payer, &punchcard],
         svm.latest_blockhash(),
     );
     svm.send_transaction(tx).unwrap();
+    let rent = svm.get_account(&punchcard.pubkey()).unwrap().lamports;
 
+    // The rent is the authority's, so a delegate's claim cannot close the card
     let tx = Transaction::new_signed_with_payer(
-        &[claim_ix(
-            &payer.pubkey(),
-            &punchcard.pubkey(),
-            vec![0, 3, 7, 12],
-        )],
+        &[claim_ix(&agent.pubkey(), &punchcard.pubkey(), vec![0, 1])],
+        Some(&agent.pubkey()),
+        &[&agent],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 2);
+    assert_eq!(svm.get_account(&punchcard.pubkey()).unwrap().lamports, rent);
+
+    let balance_before = svm.get_account(&payer.pubkey()).unwrap().lamports;
+    let tx = Transaction::new_signed_with_payer(
+        &[close_ix(&payer.pubkey(), &punchcard.pubkey())],
         Some(&payer.pubkey()),
         &[&payer],
         svm.latest_blockhash(),
     );
     svm.send_transaction(tx).unwrap();
-
-    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
-    assert_eq!(claimed, 4);
-    assert_eq!(bits[0] & (1 << 0), 1 << 0);
-    assert_eq!(bits[0] & (1 << 3), 1 << 3);
-    assert_eq!(bits[0] & (1 << 7), 1 << 7);
-    assert_eq!(bits[1] & (1 << 4), 1 << 4); // index 12 = byte 1, bit 4
+    assert_eq!(
+        svm.get_account(&payer.pubkey()).unwrap().lamports,
+        balance_before + rent - 5000
+    );
 }
 
 #[test]
-fn test_claim_already_claimed_fails() {
+fn test_claim_with_proof() {
+    use punchcard::merkle::{leaf, parent};
+
     let (mut svm, payer) = setup();
     let punchcard = Keypair::new();
+    let user = Keypair::new();
+    svm.airdrop(&user.pubkey(), 1_000_000_000).unwrap();
+
+    // Eligible indices 2, 5, 9 and 11
+    let left = parent(&leaf(2), &leaf(5));
+    let right = parent(&leaf(9), &leaf(11));
+    let root = parent(&left, &right);
 
     let tx = Transaction::new_signed_with_payer(
         &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
@@ -156,51 +3830,177 @@ fn test_claim_already_claimed_fails() {
     );
     svm.send_transaction(tx).unwrap();
 
+    // Without a root there is nothing to prove against
     let tx = Transaction::new_signed_with_payer(
-        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5])],
+        &[claim_with_proof_ix(
+            &user.pubkey(),
+            &punchcard.pubkey(),
+            9,
+            vec![leaf(11), left],
+        )],
+        Some(&user.pubkey()),
+        &[&user],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(32))
+    );
+
+    let tx = Transaction::new_signed_with_payer(
+        &[set_merkle_root_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            root,
+        )],
         Some(&payer.pubkey()),
         &[&payer],
         svm.latest_blockhash(),
     );
     svm.send_transaction(tx).unwrap();
 
+    let mut tampered = right;
+    tampered[0] ^= 1;
+    for (index, proof) in [
+        (5, vec![leaf(2), tampered]),
+        // A valid proof only covers its own index
+        (6, vec![leaf(2), right]),
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[claim_with_proof_ix(
+                &user.pubkey(),
+                &punchcard.pubkey(),
+                index,
+                proof,
+            )],
+            Some(&user.pubkey()),
+            &[&user],
+            svm.latest_blockhash(),
+        );
+        assert_eq!(
+            instruction_error(svm.send_transaction(tx)),
+            Some(InstructionError::Custom(32))
+        );
+    }
+
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_with_proof_ix(
+            &user.pubkey(),
+            &punchcard.pubkey(),
+            9,
+            vec![leaf(11), left],
+        )],
+        Some(&user.pubkey()),
+        &[&user],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+    assert_eq!(bits, vec![0, 0b0000_0010]);
+
+    // A proof is good for one claim
     let tx = Transaction::new_signed_with_payer(
-        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5])],
+        &[claim_with_proof_ix(
+            &user.pubkey(),
+            &punchcard.pubkey(),
+            9,
+            vec![leaf(11), left],
+        )],
+        Some(&user.pubkey()),
+        &[&user],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(2))
+    );
+
+    // The authority still claims directly
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![0])],
         Some(&payer.pubkey()),
         &[&payer],
         svm.latest_blockhash(),
     );
-    assert!(svm.send_transaction(tx).is_err());
+    svm.send_transaction(tx).unwrap();
+    assert_card_consistent(&svm, &punchcard.pubkey());
 }
 
 #[test]
-fn test_claim_out_of_bounds_fails() {
+fn test_claim_signed_requires_backend_signature() {
     let (mut svm, payer) = setup();
     let punchcard = Keypair::new();
+    let backend = Keypair::new();
+    let user = Keypair::new();
+    svm.airdrop(&user.pubkey(), 1_000_000_000).unwrap();
 
     let tx = Transaction::new_signed_with_payer(
-        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            set_claim_signer_ix(&payer.pubkey(), &punchcard.pubkey(), &backend.pubkey()),
+        ],
         Some(&payer.pubkey()),
         &[&payer, &punchcard],
         svm.latest_blockhash(),
     );
     svm.send_transaction(tx).unwrap();
 
+    let card = punchcard.pubkey().to_bytes();
+    let impostor = Keypair::new();
+    for instructions in [
+        // No signature at all
+        vec![claim_signed_ix(&user.pubkey(), &punchcard.pubkey(), 3)],
+        // A signature for another index
+        vec![
+            ed25519_ix(&backend, &punchcard::claim_message(&card, 4)),
+            claim_signed_ix(&user.pubkey(), &punchcard.pubkey(), 3),
+        ],
+        // The right message signed by the wrong key
+        vec![
+            ed25519_ix(&impostor, &punchcard::claim_message(&card, 3)),
+            claim_signed_ix(&user.pubkey(), &punchcard.pubkey(), 3),
+        ],
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&user.pubkey()),
+            &[&user],
+            svm.latest_blockhash(),
+        );
+        let err = svm.send_transaction(tx).unwrap_err().err;
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                instructions.len() as u8 - 1,
+                InstructionError::Custom(33)
+            )
+        );
+    }
+
     let tx = Transaction::new_signed_with_payer(
-        &[claim_ix(&payer.pubkey(), &punchcard.pubkey(), vec![16])],
-        Some(&payer.pubkey()),
-        &[&payer],
+        &[
+            ed25519_ix(&backend, &punchcard::claim_message(&card, 3)),
+            claim_signed_ix(&user.pubkey(), &punchcard.pubkey(), 3),
+        ],
+        Some(&user.pubkey()),
+        &[&user],
         svm.latest_blockhash(),
     );
-    assert!(svm.send_transaction(tx).is_err());
+    svm.send_transaction(tx).unwrap();
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+    assert_eq!(bits, vec![0b0000_1000, 0]);
 }
 
 #[test]
-fn test_claim_wrong_authority_fails() {
+fn test_claim_for_fee_pays_authority() {
     let (mut svm, payer) = setup();
     let punchcard = Keypair::new();
-    let wrong = Keypair::new();
-    svm.airdrop(&wrong.pubkey(), 1_000_000_000).unwrap();
+    let relayer = Keypair::new();
+    svm.airdrop(&relayer.pubkey(), 1_000_000_000).unwrap();
 
     let tx = Transaction::new_signed_with_payer(
         &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 16)],
@@ -210,72 +4010,265 @@ fn test_claim_wrong_authority_fails() {
     );
     svm.send_transaction(tx).unwrap();
 
+    let authority_before = svm.get_account(&payer.pubkey()).unwrap().lamports;
+    let relayer_before = svm.get_account(&relayer.pubkey()).unwrap().lamports;
     let tx = Transaction::new_signed_with_payer(
-        &[claim_ix(&wrong.pubkey(), &punchcard.pubkey(), vec![5])],
-        Some(&wrong.pubkey()),
-        &[&wrong],
+        &[claim_for_fee_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            &relayer.pubkey(),
+            vec![1, 2, 3],
+            10_000,
+        )],
+        Some(&relayer.pubkey()),
+        &[&relayer, &payer],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    assert_eq!(
+        svm.get_account(&payer.pubkey()).unwrap().lamports,
+        authority_before + 30_000
+    );
+    // Two signatures' worth of transaction fees on top of the claim fee
+    assert_eq!(
+        svm.get_account(&relayer.pubkey()).unwrap().lamports,
+        relayer_before - 30_000 - 10_000
+    );
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 3);
+
+    // A payer that cannot cover the fee fails the claim with it
+    let broke = Keypair::new();
+    svm.airdrop(&broke.pubkey(), 1_000_000).unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[claim_for_fee_ix(
+            &payer.pubkey(),
+            &punchcard.pubkey(),
+            &broke.pubkey(),
+            vec![4, 5],
+            1_000_000,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &broke],
         svm.latest_blockhash(),
     );
     assert!(svm.send_transaction(tx).is_err());
+
+    let (_, _, claimed, _) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 3);
+    assert_eq!(
+        svm.get_account(&broke.pubkey()).unwrap().lamports,
+        1_000_000
+    );
 }
 
 #[test]
-fn test_claim_all_closes_account() {
+fn test_toggle() {
     let (mut svm, payer) = setup();
     let punchcard = Keypair::new();
 
     let tx = Transaction::new_signed_with_payer(
-        &[create_ix(&payer.pubkey(), &punchcard.pubkey(), 4)],
+        &[
+            create_ix(&payer.pubkey(), &punchcard.pubkey(), 16),
+            toggle_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5, 9]),
+        ],
         Some(&payer.pubkey()),
         &[&payer, &punchcard],
         svm.latest_blockhash(),
     );
     svm.send_transaction(tx).unwrap();
 
-    let balance_before = svm.get_account(&payer.pubkey()).unwrap().lamports;
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 2);
+    assert_eq!(bits, vec![0b0010_0000, 0b0000_0010]);
+    assert_card_consistent(&svm, &punchcard.pubkey());
 
     let tx = Transaction::new_signed_with_payer(
-        &[claim_ix(
-            &payer.pubkey(),
-            &punchcard.pubkey(),
-            vec![0, 1, 2, 3],
-        )],
+        &[toggle_ix(&payer.pubkey(), &punchcard.pubkey(), vec![5])],
         Some(&payer.pubkey()),
         &[&payer],
         svm.latest_blockhash(),
     );
     svm.send_transaction(tx).unwrap();
 
-    // Account should be closed
-    match svm.get_account(&punchcard.pubkey()) {
-        None => {}
-        Some(acc) => {
-            assert!(acc.lamports == 0 || acc.owner == Pubkey::new_from_array(pinocchio_system::ID))
-        }
-    }
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+    assert_eq!(bits, vec![0, 0b0000_0010]);
+    assert_card_consistent(&svm, &punchcard.pubkey());
 
-    // Rent returned
-    let balance_after = svm.get_account(&payer.pubkey()).unwrap().lamports;
-    assert!(balance_after > balance_before);
+    // Only the authority can toggle
+    let stranger = Keypair::new();
+    svm.airdrop(&stranger.pubkey(), 1_000_000_000).unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[toggle_ix(&stranger.pubkey(), &punchcard.pubkey(), vec![5])],
+        Some(&stranger.pubkey()),
+        &[&stranger],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(0))
+    );
+
+    let tx = Transaction::new_signed_with_payer(
+        &[toggle_ix(&payer.pubkey(), &punchcard.pubkey(), vec![16])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::Custom(1))
+    );
 }
 
 #[test]
-fn test_various_capacities() {
+fn test_bound_recipients_claim_their_own_index() {
     let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let alice = Keypair::new();
+    let bob = Keypair::new();
 
-    for capacity in [1, 7, 8, 9, 15, 16, 17, 64, 100] {
-        let punchcard = Keypair::new();
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_with_recipients_ix(&payer.pubkey(), &punchcard.pubkey(), 4),
+            set_recipients_ix(
+                &payer.pubkey(),
+                &punchcard.pubkey(),
+                0,
+                &[alice.pubkey(), bob.pubkey()],
+            ),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+    let account = svm.get_account(&punchcard.pubkey()).unwrap();
+    assert_eq!(account.data.len(), HEADER_LEN + 1 + 4 * 32);
+
+    let claim = |svm: &mut LiteSVM, claimer: &Keypair, index: u64| {
+        let signers = if claimer.pubkey() == payer.pubkey() {
+            vec![&payer]
+        } else {
+            vec![&payer, claimer]
+        };
         let tx = Transaction::new_signed_with_payer(
-            &[create_ix(&payer.pubkey(), &punchcard.pubkey(), capacity)],
+            &[claim_ix(
+                &claimer.pubkey(),
+                &punchcard.pubkey(),
+                vec![index],
+            )],
             Some(&payer.pubkey()),
-            &[&payer, &punchcard],
+            &signers[..],
             svm.latest_blockhash(),
         );
-        svm.send_transaction(tx).unwrap();
+        svm.send_transaction(tx)
+    };
 
-        let (_, cap, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
-        assert_eq!(cap, capacity);
-        assert_eq!(claimed, 0);
-        assert_eq!(bits.len(), ((capacity + 7) / 8) as usize);
-    }
+    // Neither someone else's recipient nor the authority can take a bound index
+    assert_eq!(
+        instruction_error(claim(&mut svm, &bob, 0)),
+        Some(InstructionError::Custom(0))
+    );
+    assert_eq!(
+        instruction_error(claim(&mut svm, &payer, 1)),
+        Some(InstructionError::Custom(0))
+    );
+
+    claim(&mut svm, &alice, 0).unwrap();
+    claim(&mut svm, &bob, 1).unwrap();
+
+    // Unbound indices are claimed as on any other card
+    assert_eq!(
+        instruction_error(claim(&mut svm, &alice, 2)),
+        Some(InstructionError::Custom(0))
+    );
+    claim(&mut svm, &payer, 2).unwrap();
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 3);
+    assert_eq!(bits, vec![0b0000_0111]);
+    assert_card_consistent(&svm, &punchcard.pubkey());
+
+    // Cards made without recipients have nowhere to bind them
+    let plain = Keypair::new();
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_ix(&payer.pubkey(), &plain.pubkey(), 4),
+            set_recipients_ix(&payer.pubkey(), &plain.pubkey(), 0, &[alice.pubkey()]),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &plain],
+        svm.latest_blockhash(),
+    );
+    assert_eq!(
+        instruction_error(svm.send_transaction(tx)),
+        Some(InstructionError::InvalidAccountData)
+    );
+}
+
+#[test]
+fn test_bound_recipient_touches_its_own_index() {
+    let (mut svm, payer) = setup();
+    let punchcard = Keypair::new();
+    let alice = Keypair::new();
+    let bob = Keypair::new();
+
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            create_with_recipients_ix(&payer.pubkey(), &punchcard.pubkey(), 4),
+            set_recipients_ix(
+                &payer.pubkey(),
+                &punchcard.pubkey(),
+                0,
+                &[alice.pubkey(), bob.pubkey()],
+            ),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &punchcard],
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+
+    let touch = |svm: &mut LiteSVM, toucher: &Keypair, index: u64| {
+        let signers = if toucher.pubkey() == payer.pubkey() {
+            vec![&payer]
+        } else {
+            vec![&payer, toucher]
+        };
+        svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[touch_claim_ix(
+                &toucher.pubkey(),
+                &punchcard.pubkey(),
+                index,
+            )],
+            Some(&payer.pubkey()),
+            &signers[..],
+            svm.latest_blockhash(),
+        );
+        svm.send_transaction(tx)
+    };
+
+    // TouchClaim follows the same binding rule as Claim
+    assert_eq!(
+        instruction_error(touch(&mut svm, &bob, 0)),
+        Some(InstructionError::Custom(0))
+    );
+    assert_eq!(
+        instruction_error(touch(&mut svm, &payer, 1)),
+        Some(InstructionError::Custom(0))
+    );
+
+    let meta = touch(&mut svm, &alice, 0).unwrap();
+    assert_eq!(meta.return_data.data, vec![1]);
+    let meta = touch(&mut svm, &alice, 0).unwrap();
+    assert_eq!(meta.return_data.data, vec![0]);
+
+    let (_, _, claimed, bits) = read_punchcard(&svm, &punchcard.pubkey()).unwrap();
+    assert_eq!(claimed, 1);
+    assert_eq!(bits, vec![0b0000_0001]);
+    assert_card_consistent(&svm, &punchcard.pubkey());
 }
@@ -0,0 +1,40 @@
+//! Test-only program that gates its own logic on a punchcard claim by CPI into
+//! `RequireClaimed`.
+
+use pinocchio::{
+    ProgramResult,
+    account_info::AccountInfo,
+    cpi::invoke,
+    entrypoint,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+entrypoint!(process);
+
+/// Accounts: `[punchcard program, punchcard]`. Data: the index as a `u64` LE.
+/// Succeeds, returning `b"open"`, only if the index is claimed.
+pub fn process(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let [punchcard_program, punchcard] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    let index = u64::from_le_bytes(
+        data.try_into()
+            .map_err(|_| ProgramError::InvalidInstructionData)?,
+    );
+
+    let data = borsh::to_vec(&punchcard::Instruction::RequireClaimed { index })
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    invoke(
+        &Instruction {
+            program_id: punchcard_program.key(),
+            accounts: &[AccountMeta::readonly(punchcard.key())],
+            data: &data,
+        },
+        &[punchcard],
+    )?;
+
+    pinocchio::cpi::set_return_data(b"open");
+    Ok(())
+}